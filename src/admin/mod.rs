@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    serde::json::Json,
+    Request, State,
+};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::{
+    config::{self, Secret},
+    store::{
+        ActionEntry, ActionFilter, JobFilter, JobLog, JobStatus, JobSummary, PlannedOperationEntry,
+        PlannedOperationFilter, Store,
+    },
+};
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+/// Request guard admitting requests that carry the configured `admin.token` as a bearer token.
+pub struct Admin;
+
+fn authorize(
+    authorization: Option<&str>,
+    token: Option<&Secret>,
+) -> Result<(), (Status, anyhow::Error)> {
+    let token = token.ok_or_else(|| (Status::NotFound, anyhow!("Admin API is disabled.")))?;
+    let provided = authorization
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(|| (Status::Unauthorized, anyhow!("Missing bearer token.")))?;
+    // Comparing digests rather than the tokens themselves keeps the comparison time independent of
+    // how much of the token matches.
+    if Sha256::digest(provided.as_bytes()) == Sha256::digest(token.expose().as_bytes()) {
+        Ok(())
+    } else {
+        Err((Status::Forbidden, anyhow!("Invalid admin token.")))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = anyhow::Error;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match authorize(
+            req.headers().get_one("Authorization"),
+            config::get().admin.token.as_ref(),
+        ) {
+            Ok(()) => request::Outcome::Success(Admin),
+            Err(err) => request::Outcome::Error(err),
+        }
+    }
+}
+
+fn limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+}
+
+fn internal_error(err: anyhow::Error) -> Status {
+    error!(error = ?err, "Admin API query failed");
+    Status::InternalServerError
+}
+
+/// Lists the most recent jobs, optionally filtered by repo, pull request, command and status.
+#[get("/v1/admin/jobs?<repo>&<pr>&<command>&<status>&<limit>")]
+pub fn jobs(
+    _admin: Admin,
+    store: &State<Arc<Store>>,
+    repo: Option<&str>,
+    pr: Option<u64>,
+    command: Option<&str>,
+    status: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Json<Vec<JobSummary>>, Status> {
+    let status = status
+        .map(|status| status.parse::<JobStatus>())
+        .transpose()
+        .map_err(|_| Status::BadRequest)?;
+    let filter = JobFilter {
+        repo,
+        pull_request: pr,
+        command,
+        status,
+        limit: self::limit(limit),
+    };
+    store.list_jobs(&filter).map(Json).map_err(internal_error)
+}
+
+/// Fetches a job along with its recorded actions and the output of the processes it spawned.
+#[get("/v1/admin/jobs/<id>")]
+pub fn job(_admin: Admin, store: &State<Arc<Store>>, id: &str) -> Result<Json<JobLog>, Status> {
+    store
+        .job_log(id)
+        .map_err(internal_error)?
+        .map(Json)
+        .ok_or(Status::NotFound)
+}
+
+/// Lists the most recent actions and who authorized them.
+#[get("/v1/admin/actions?<repo>&<user>&<command>&<limit>")]
+pub fn actions(
+    _admin: Admin,
+    store: &State<Arc<Store>>,
+    repo: Option<&str>,
+    user: Option<&str>,
+    command: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Json<Vec<ActionEntry>>, Status> {
+    let filter = ActionFilter {
+        repo,
+        authorized_by: user,
+        command,
+        limit: self::limit(limit),
+    };
+    store
+        .list_actions(&filter)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Lists the most recent operations skipped in dry-run mode.
+#[get("/v1/admin/planned-operations?<repo>&<job>&<limit>")]
+pub fn planned_operations(
+    _admin: Admin,
+    store: &State<Arc<Store>>,
+    repo: Option<&str>,
+    job: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Json<Vec<PlannedOperationEntry>>, Status> {
+    let filter = PlannedOperationFilter {
+        repo,
+        job_id: job,
+        limit: self::limit(limit),
+    };
+    store
+        .list_planned_operations(&filter)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use serde::Deserialize;
+
+    use super::authorize;
+    use crate::config::Secret;
+
+    fn status(authorization: Option<&str>, token: Option<&str>) -> Option<Status> {
+        let token = token
+            .map(|token| Secret::deserialize(serde_json::Value::String(token.to_owned())).unwrap());
+        authorize(authorization, token.as_ref())
+            .err()
+            .map(|(status, _)| status)
+    }
+
+    #[test]
+    fn it_should_accept_configured_token() {
+        assert_eq!(status(Some("Bearer hunter2"), Some("hunter2")), None);
+    }
+
+    #[test]
+    fn it_should_reject_missing_or_invalid_token() {
+        assert_eq!(status(None, Some("hunter2")), Some(Status::Unauthorized));
+        assert_eq!(
+            status(Some("token hunter2"), Some("hunter2")),
+            Some(Status::Unauthorized)
+        );
+        assert_eq!(
+            status(Some("Bearer hunter3"), Some("hunter2")),
+            Some(Status::Forbidden)
+        );
+    }
+
+    #[test]
+    fn it_should_be_disabled_without_token() {
+        assert_eq!(status(Some("Bearer hunter2"), None), Some(Status::NotFound));
+    }
+}
@@ -0,0 +1,767 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug},
+    fs,
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+use jsonwebtoken::EncodingKey;
+use rocket::figment::{
+    providers::{Env, Format, Toml},
+    Figment,
+};
+use serde::{de, Deserialize};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    github::data::{GitHubIssuesEventAction, GitHubRepoId, GitHubRepoPermission},
+    health::Version,
+};
+
+const DEFAULT_CONFIG_PATH: &str = "botman.toml";
+const DEFAULT_STATE_DIR: &str = "state";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Installs the validated configuration. Must be called once, before Rocket launches.
+pub fn init(config: Config) -> Result<()> {
+    CONFIG
+        .set(config)
+        .map_err(|_| anyhow!("Configuration has already been initialized."))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG
+        .get()
+        .expect("Configuration has not been initialized.")
+}
+
+/// A secret value, either provided inline or read from a file (`{ file = "/run/secrets/…" }`).
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SecretSource {
+            Inline(String),
+            File { file: PathBuf },
+        }
+
+        match SecretSource::deserialize(deserializer)? {
+            SecretSource::Inline(value) => Ok(Secret(value)),
+            SecretSource::File { file } => fs::read_to_string(&file)
+                .map(|contents| Secret(contents.trim_end().to_owned()))
+                .map_err(|err| {
+                    de::Error::custom(format!("failed to read secret {}: {}", file.display(), err))
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitHubAppConfig {
+    pub id: u64,
+    /// The PEM-encoded RSA private key of the app.
+    pub private_key: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitHubConfig {
+    /// The login of the bot account, used for mentions and to recognize its own activity.
+    pub login: String,
+    /// Other names the bot answers to when mentioned, e.g. `botman`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Personal access token. Mutually exclusive with `app`.
+    pub token: Option<Secret>,
+    /// Authenticate as a GitHub App using per-installation access tokens. Mutually exclusive with
+    /// `token`.
+    pub app: Option<GitHubAppConfig>,
+    /// Base URL of the REST API, e.g. `https://github.example.com/api/v3` for GitHub Enterprise
+    /// Server.
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// URL of the GraphQL API, e.g. `https://github.example.com/api/graphql`.
+    #[serde(default = "default_graphql_url")]
+    pub graphql_url: String,
+    /// Base URL of git remotes, e.g. `https://github.example.com`.
+    #[serde(default = "default_git_url")]
+    pub git_url: String,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// How requests to GitHub are retried after transient failures and rate limits.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// How many times a request is attempted in total.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every following one.
+    pub initial_delay_ms: u64,
+    /// Upper bound of the backoff. Requests are not retried if a rate limit asks to wait longer.
+    pub max_delay_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay_ms: 500,
+            max_delay_secs: 60,
+        }
+    }
+}
+
+fn default_api_url() -> String {
+    "https://api.github.com".to_owned()
+}
+
+fn default_graphql_url() -> String {
+    "https://api.github.com/graphql".to_owned()
+}
+
+fn default_git_url() -> String {
+    "https://github.com".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub secret: Option<Secret>,
+    /// Additional accepted secrets, so that the secret can be rotated without downtime.
+    #[serde(default)]
+    pub secrets: Vec<Secret>,
+    /// Also accept legacy HMAC-SHA1 signatures from the `X-Hub-Signature` header.
+    #[serde(default)]
+    pub allow_sha1: bool,
+    /// How many delivery IDs to remember for deduplicating redelivered webhooks.
+    #[serde(default = "default_delivery_history")]
+    pub delivery_history: usize,
+    /// Reject deliveries whose triggering event is older than this many seconds.
+    pub max_delivery_age: Option<u64>,
+}
+
+impl WebhookConfig {
+    /// All secrets a webhook signature may be verified with.
+    pub fn accepted_secrets(&self) -> Vec<&Secret> {
+        self.secret.iter().chain(self.secrets.iter()).collect()
+    }
+}
+
+fn default_delivery_history() -> usize {
+    10_000
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line, including the fields of the enclosing spans.
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Which logs to emit, in `tracing_subscriber::EnvFilter` syntax (e.g. `info,botman=debug`).
+    /// Overridden by `RUST_LOG`.
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            filter: "info".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AdminConfig {
+    /// Bearer token granting access to the admin API. The admin API is disabled when unset.
+    pub token: Option<Secret>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// How many jobs (e.g. commands requested in comments) may run concurrently.
+    pub workers: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self { workers: 4 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReadinessConfig {
+    /// External tools that must be on `PATH`, mapped to the minimum version they must report with
+    /// `--version`.
+    pub tools: BTreeMap<String, String>,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            tools: [
+                ("git", "2.0"),
+                ("make", "4.0"),
+                ("nvim", "0.10.1"),
+                ("stylua", "0.20.0"),
+            ]
+            .into_iter()
+            .map(|(tool, version)| (tool.to_owned(), version.to_owned()))
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HacktoberfestConfig {
+    pub enabled: bool,
+    /// Authors whose merged pull requests are never labeled. The bot's own login is always
+    /// excluded.
+    pub excluded_authors: Vec<String>,
+    /// Start of the labeling window, as `MM-DD`.
+    pub start: String,
+    /// End of the labeling window (inclusive), as `MM-DD`.
+    pub end: String,
+}
+
+impl Default for HacktoberfestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            excluded_authors: vec!["renovate[bot]".to_owned()],
+            start: "09-25".to_owned(),
+            end: "11-05".to_owned(),
+        }
+    }
+}
+
+impl HacktoberfestConfig {
+    /// Returns the labeling window for the given year.
+    pub fn window(&self, year: i32) -> Result<(NaiveDate, NaiveDate)> {
+        Ok((
+            parse_month_day(year, &self.start)?,
+            parse_month_day(year, &self.end)?,
+        ))
+    }
+}
+
+fn parse_month_day(year: i32, month_day: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-{}", year, month_day), "%Y-%m-%d")
+        .map_err(|err| anyhow!("{} is not a valid MM-DD date: {}", month_day, err))
+}
+
+/// Who may run a command.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandPermission {
+    /// Only the repo's maintainers.
+    Maintainer,
+    /// The repo's maintainers and the author of the pull request the command is run on.
+    PullRequestAuthor,
+    /// Anyone who can comment.
+    Anyone,
+}
+
+/// Where an issue or pull request stands on the triage project.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProjectStatus {
+    Prioritized,
+    Triage,
+    Backlog,
+    Support,
+    Closed,
+}
+
+impl ProjectStatus {
+    fn default_option_name(self) -> &'static str {
+        match self {
+            Self::Prioritized => "Prioritized",
+            Self::Triage => "Triage",
+            Self::Backlog => "Backlog",
+            Self::Support => "Support",
+            Self::Closed => "Closed",
+        }
+    }
+}
+
+fn default_status_field() -> String {
+    "Status".to_owned()
+}
+
+pub(crate) fn default_project_actions() -> BTreeMap<GitHubIssuesEventAction, ProjectStatus> {
+    BTreeMap::from([
+        (GitHubIssuesEventAction::Opened, ProjectStatus::Triage),
+        (GitHubIssuesEventAction::Closed, ProjectStatus::Closed),
+        (GitHubIssuesEventAction::Reopened, ProjectStatus::Triage),
+    ])
+}
+
+/// A Projects V2 board issues are triaged on. The project and its status field are looked up by
+/// name.
+#[derive(Debug, Deserialize)]
+pub struct ProjectConfig {
+    /// Login of the user or organization owning the project.
+    pub owner: String,
+    pub title: String,
+    /// Name of the single select field holding the status of items.
+    #[serde(default = "default_status_field")]
+    pub status_field: String,
+    /// Names of the status field's options, where they differ from the status names.
+    #[serde(default)]
+    pub statuses: BTreeMap<ProjectStatus, String>,
+    /// The status issues are moved to by issue events, e.g. `closed = "closed"`.
+    #[serde(default = "default_project_actions")]
+    pub actions: BTreeMap<GitHubIssuesEventAction, ProjectStatus>,
+    /// The status issues are moved to when labeled, or opened with a label. Takes precedence over
+    /// `actions`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, ProjectStatus>,
+}
+
+impl ProjectConfig {
+    pub fn option_name(&self, status: ProjectStatus) -> &str {
+        self.statuses
+            .get(&status)
+            .map_or(status.default_option_name(), String::as_str)
+    }
+}
+
+/// The set of commands and triage behaviour a repo's webhooks are handled with.
+#[derive(Debug, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HandlerKind {
+    Mason,
+    MasonRegistry,
+    /// `/apply` and `/merge-base`, plus triage notifications if a `triage_team` is set.
+    #[default]
+    Generic,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct RepoConfig {
+    pub handler: HandlerKind,
+    /// Users allowed to run bot commands in this repo.
+    pub maintainers: Vec<String>,
+    /// Teams, as `org/team-slug`, whose members may run bot commands in this repo.
+    pub maintainer_teams: Vec<String>,
+    /// Collaborators with at least this permission on the repo (e.g. `write`) may run bot commands.
+    pub maintainer_permission: Option<GitHubRepoPermission>,
+    /// Overrides who may run a command, by command name (e.g. `fixup`).
+    pub command_permissions: BTreeMap<String, CommandPermission>,
+    /// Accept commands without a mention, as a `/command` at the start of the comment.
+    pub bare_commands: bool,
+    /// Team whose review is requested when triage is needed.
+    pub triage_team: Option<String>,
+    /// Authors of automated dependency update pull requests (e.g. `renovate[bot]`).
+    pub dependency_bots: Vec<String>,
+    pub project: Option<ProjectConfig>,
+}
+
+fn default_state_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_STATE_DIR)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub github: GitHubConfig,
+    pub webhook: WebhookConfig,
+    /// Directory where botman persists its state across restarts.
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+    /// Skip every GitHub mutation and push, recording them as planned operations instead.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+    #[serde(default)]
+    pub hacktoberfest: HacktoberfestConfig,
+    /// Per-repo settings, keyed by `owner/name`.
+    #[serde(default)]
+    pub repos: HashMap<String, RepoConfig>,
+}
+
+impl Config {
+    /// Loads the configuration from the TOML file at `$BOTMAN_CONFIG` (default `botman.toml`),
+    /// overridden by `BOTMAN_*` environment variables (nested keys are separated by `__`, e.g.
+    /// `BOTMAN_GITHUB__LOGIN`). The legacy `GITHUB_LOGIN`, `GITHUB_PAT` and
+    /// `GITHUB_WEBHOOK_SECRET` variables are still honoured.
+    pub fn load() -> Result<Config> {
+        let path =
+            std::env::var("BOTMAN_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_owned());
+        let figment = Figment::new()
+            .merge(Toml::file(&path))
+            .merge(
+                Env::raw()
+                    .only(&["GITHUB_LOGIN", "GITHUB_PAT", "GITHUB_WEBHOOK_SECRET"])
+                    .map(|key| match key.as_str() {
+                        "github_login" => "github.login".into(),
+                        "github_pat" => "github.token".into(),
+                        _ => "webhook.secret".into(),
+                    }),
+            )
+            .merge(Env::prefixed("BOTMAN_").ignore(&["CONFIG"]).split("__"));
+        Self::from_figment(figment)
+    }
+
+    pub fn from_figment(figment: Figment) -> Result<Config> {
+        let config: Config = figment
+            .extract()
+            .map_err(|err| anyhow!("Invalid configuration: {}", err))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+
+        if self.github.login.trim().is_empty() {
+            errors.push("github.login must not be empty.".to_owned());
+        }
+        for alias in &self.github.aliases {
+            let name = alias.strip_prefix('@').unwrap_or(alias);
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                errors.push(format!(
+                    "github.aliases: \"{}\" is not a valid mention.",
+                    alias
+                ));
+            }
+        }
+        match (&self.github.token, &self.github.app) {
+            (Some(token), None) if token.expose().is_empty() => {
+                errors.push("github.token must not be empty.".to_owned())
+            }
+            (None, Some(app)) => {
+                if let Err(err) = EncodingKey::from_rsa_pem(app.private_key.expose().as_bytes()) {
+                    errors.push(format!(
+                        "github.app.private_key is not a valid RSA key: {}",
+                        err
+                    ));
+                }
+            }
+            (Some(_), None) => {}
+            (Some(_), Some(_)) => {
+                errors.push("github.token and github.app are mutually exclusive.".to_owned())
+            }
+            (None, None) => {
+                errors.push("Either github.token or github.app must be set.".to_owned())
+            }
+        }
+        let secrets = self.webhook.accepted_secrets();
+        if secrets.is_empty() {
+            errors.push("Either webhook.secret or webhook.secrets must be set.".to_owned());
+        }
+        if secrets.iter().any(|secret| secret.expose().is_empty()) {
+            errors.push("webhook.secret and webhook.secrets must not be empty.".to_owned());
+        }
+        if self.webhook.delivery_history == 0 {
+            errors.push("webhook.delivery_history must be greater than 0.".to_owned());
+        }
+        if matches!(&self.admin.token, Some(token) if token.expose().is_empty()) {
+            errors.push("admin.token must not be empty.".to_owned());
+        }
+        if let Err(err) = EnvFilter::try_new(&self.log.filter) {
+            errors.push(format!("log.filter is invalid: {}", err));
+        }
+        for (key, url) in [
+            ("api_url", &self.github.api_url),
+            ("graphql_url", &self.github.graphql_url),
+            ("git_url", &self.github.git_url),
+        ] {
+            if reqwest::Url::parse(url).is_err() {
+                errors.push(format!("github.{} ({}) is not a valid URL.", key, url));
+            }
+        }
+        if self.github.retry.max_attempts == 0 {
+            errors.push("github.retry.max_attempts must be greater than 0.".to_owned());
+        }
+        for (tool, version) in &self.readiness.tools {
+            if version.parse::<Version>().is_err() {
+                errors.push(format!(
+                    "readiness.tools.{} ({}) is not a valid version.",
+                    tool, version
+                ));
+            }
+        }
+        if self.jobs.workers == 0 {
+            errors.push("jobs.workers must be greater than 0.".to_owned());
+        }
+        match self.hacktoberfest.window(2000) {
+            Ok((start, end)) if start > end => errors.push(format!(
+                "hacktoberfest.start ({}) must not be after hacktoberfest.end ({}).",
+                self.hacktoberfest.start, self.hacktoberfest.end
+            )),
+            Ok(_) => {}
+            Err(err) => errors.push(format!("hacktoberfest: {}", err)),
+        }
+        for (key, repo) in &self.repos {
+            if key.parse::<GitHubRepoId>().is_err() {
+                errors.push(format!("repos.\"{}\" is not an owner/name repo.", key));
+            }
+            for team in &repo.maintainer_teams {
+                if !matches!(team.split_once('/'), Some((org, slug)) if !org.is_empty() && !slug.is_empty())
+                {
+                    errors.push(format!(
+                        "repos.\"{}\".maintainer_teams: {} is not an org/team-slug team.",
+                        key, team
+                    ));
+                }
+            }
+            if let Some(project) = &repo.project {
+                if project.owner.is_empty() || project.title.is_empty() {
+                    errors.push(format!(
+                        "repos.\"{}\".project.owner and title must not be empty.",
+                        key
+                    ));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            bail!("Invalid configuration:\n  {}", errors.join("\n  "))
+        }
+    }
+
+    pub fn repo(&self, repo: &GitHubRepoId) -> Option<&RepoConfig> {
+        self.repos.get(&repo.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, HandlerKind};
+    use indoc::indoc;
+    use rocket::figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+
+    fn load(toml: &str) -> anyhow::Result<Config> {
+        Config::from_figment(Figment::from(Toml::string(toml)))
+    }
+
+    #[test]
+    fn it_should_load_minimal_config_with_defaults() {
+        let config = load(indoc! {r#"
+            [github]
+            login = "williambotman"
+            token = "ghp_secret"
+
+            [webhook]
+            secret = "hunter2"
+
+            [repos."williamboman/mason.nvim"]
+            handler = "mason"
+            maintainers = ["williamboman"]
+
+            [repos."williamboman/botman"]
+        "#})
+        .unwrap();
+
+        assert_eq!(config.github.login, "williambotman");
+        assert_eq!(config.github.token.as_ref().unwrap().expose(), "ghp_secret");
+        assert_eq!(format!("{:?}", config.webhook.secret), "Some(Secret(***))");
+        assert!(!config.webhook.allow_sha1);
+        assert!(config.hacktoberfest.enabled);
+        assert_eq!(
+            config
+                .repo(&"williamboman/mason.nvim".parse().unwrap())
+                .unwrap()
+                .maintainers,
+            vec!["williamboman"]
+        );
+        assert_eq!(
+            config
+                .repo(&"williamboman/mason.nvim".parse().unwrap())
+                .unwrap()
+                .handler,
+            HandlerKind::Mason
+        );
+        assert_eq!(
+            config
+                .repo(&"williamboman/botman".parse().unwrap())
+                .unwrap()
+                .handler,
+            HandlerKind::Generic
+        );
+        assert!(config
+            .repo(&"williamboman/nvim-lsp-installer".parse().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn it_should_read_secrets_from_files() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"from-file\n").unwrap();
+        let config = load(&format!(
+            indoc! {r#"
+                [github]
+                login = "williambotman"
+                token = {{ file = "{}" }}
+
+                [webhook]
+                secret = "hunter2"
+            "#},
+            file.path().display()
+        ))
+        .unwrap();
+
+        assert_eq!(config.github.token.as_ref().unwrap().expose(), "from-file");
+    }
+
+    #[test]
+    fn it_should_report_all_validation_errors() {
+        let err = load(indoc! {r#"
+            [github]
+            login = ""
+            aliases = ["bot man"]
+            token = "ghp_secret"
+
+            [webhook]
+            secret = ""
+
+            [hacktoberfest]
+            start = "11-05"
+            end = "09-25"
+
+            [repos.mason]
+        "#})
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("github.login"), "{}", err);
+        assert!(err.contains("github.aliases"), "{}", err);
+        assert!(err.contains("webhook.secret"), "{}", err);
+        assert!(err.contains("hacktoberfest.start"), "{}", err);
+        assert!(err.contains("repos.\"mason\""), "{}", err);
+    }
+
+    #[test]
+    fn it_should_load_github_app_credentials() {
+        let config = load(&format!(
+            indoc! {r#"
+                [github]
+                login = "williambotman"
+                app = {{ id = 1337, private_key = {{ file = "{}" }} }}
+
+                [webhook]
+                secret = "hunter2"
+            "#},
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/github/testdata/app-private-key.pem"
+            )
+        ))
+        .unwrap();
+
+        assert_eq!(config.github.app.unwrap().id, 1337);
+        assert!(config.github.token.is_none());
+    }
+
+    #[test]
+    fn it_should_reject_ambiguous_or_invalid_app_credentials() {
+        let err = load(indoc! {r#"
+            [github]
+            login = "williambotman"
+            token = "ghp_secret"
+            app = { id = 1337, private_key = "not a key" }
+
+            [webhook]
+            secret = "hunter2"
+        "#})
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("mutually exclusive"), "{}", err);
+
+        let err = load(indoc! {r#"
+            [github]
+            login = "williambotman"
+            app = { id = 1337, private_key = "not a key" }
+
+            [webhook]
+            secret = "hunter2"
+        "#})
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("github.app.private_key"), "{}", err);
+    }
+
+    #[test]
+    fn it_should_accept_multiple_webhook_secrets() {
+        let config = load(indoc! {r#"
+            [github]
+            login = "williambotman"
+            token = "ghp_secret"
+
+            [webhook]
+            secret = "current"
+            secrets = ["previous"]
+            allow_sha1 = true
+        "#})
+        .unwrap();
+
+        let secrets = config.webhook.accepted_secrets();
+        assert_eq!(
+            secrets.iter().map(|s| s.expose()).collect::<Vec<_>>(),
+            vec!["current", "previous"]
+        );
+        assert!(config.webhook.allow_sha1);
+
+        let err = load(indoc! {r#"
+            [github]
+            login = "williambotman"
+            token = "ghp_secret"
+
+            [webhook]
+        "#})
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("webhook.secrets"), "{}", err);
+    }
+
+    #[test]
+    fn it_should_reject_missing_required_settings() {
+        assert!(load("[github]\nlogin = \"williambotman\"").is_err());
+    }
+}
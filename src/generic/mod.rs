@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+use crate::{
+    github::{
+        action::{
+            self,
+            common::GitApplyPatch,
+            parser::{AuthorizedActionExecutor, RawCommand},
+            spec::{self, CommandSpec},
+        },
+        client::GitHubClient,
+        data::GitHubWebhook,
+        webhook::registry::{Disposition, WebhookHandler},
+    },
+    jobs::JobContext,
+    triage,
+    workspace::Workspace,
+};
+use anyhow::{bail, Result};
+use rocket::http::Status;
+
+/// Commands available in any repo that doesn't have a dedicated handler set.
+static COMMANDS: &[&CommandSpec] = &[&action::apply::APPLY, &action::merge_base::MERGE_BASE];
+
+#[derive(Debug)]
+enum GenericCommand {
+    Apply(GitApplyPatch),
+    MergeBase,
+}
+
+impl TryFrom<RawCommand> for GenericCommand {
+    type Error = anyhow::Error;
+
+    fn try_from(value: RawCommand) -> Result<Self, Self::Error> {
+        let (spec, args) = spec::parse(GenericCommand::specs(), value)?;
+        match spec.name {
+            "apply" => Ok(Self::Apply(
+                args.arg("patch")
+                    .unwrap_or_default()
+                    .to_owned()
+                    .try_into()?,
+            )),
+            "merge-base" => Ok(Self::MergeBase),
+            name => bail!("/{} is not implemented.", name),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthorizedActionExecutor for GenericCommand {
+    fn specs() -> &'static [&'static CommandSpec] {
+        COMMANDS
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        match self {
+            GenericCommand::Apply(_) => &action::apply::APPLY,
+            GenericCommand::MergeBase => &action::merge_base::MERGE_BASE,
+        }
+    }
+
+    async fn execute(&self, workspace: &Workspace) -> Result<()> {
+        match self {
+            GenericCommand::Apply(patch) => action::apply::run(workspace, patch).await,
+            GenericCommand::MergeBase => action::merge_base::run(workspace).await,
+        }
+    }
+}
+
+pub struct GenericHandler {
+    pub client: GitHubClient,
+}
+
+/// Triages issues, pull requests and check runs, and accepts the `Command`s requested in comments.
+/// Shared by the handler sets that only differ in their commands.
+pub async fn handle<Command>(client: &GitHubClient, webhook: GitHubWebhook) -> Disposition
+where
+    Command: AuthorizedActionExecutor,
+{
+    let status = match webhook {
+        GitHubWebhook::Issues(event) => triage::issue_event(client, event).await,
+        GitHubWebhook::CheckRun(event) => triage::check_run_event(client, event).await,
+        GitHubWebhook::PullRequest(event) => triage::pull_request(client, event).await,
+        webhook => return action::accept::<Command>(client, webhook).await,
+    };
+    Disposition::Handled(status.unwrap_or(Status::InternalServerError))
+}
+
+#[async_trait]
+impl WebhookHandler for GenericHandler {
+    async fn handle(&self, webhook: GitHubWebhook) -> Disposition {
+        handle::<GenericCommand>(&self.client, webhook).await
+    }
+
+    async fn run(
+        &self,
+        webhook: GitHubWebhook,
+        job: JobContext,
+    ) -> Result<Box<dyn Display + Send>> {
+        action::run::<GenericCommand>(&self.client, webhook, job).await
+    }
+}
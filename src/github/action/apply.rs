@@ -1,12 +1,28 @@
-use crate::{github::action::parser::AuthorizedAction, workspace::Workspace};
+use crate::{config::CommandPermission, workspace::Workspace};
 use anyhow::Result;
-use rocket::http::Status;
-use std::fmt::Display;
+use tracing::info;
 
-use super::{common::GitApplyPatch, parser::RawCommand};
+use super::{
+    common::GitApplyPatch,
+    spec::{ArgSpec, CommandSpec},
+};
+
+pub static APPLY: CommandSpec = CommandSpec {
+    name: "apply",
+    aliases: &[],
+    args: &[ArgSpec {
+        name: "patch",
+        description: "A ```diff block on the lines following the command.",
+        required: true,
+        block: true,
+    }],
+    flags: &[],
+    description: "Applies a patch to the pull request.",
+    permission: CommandPermission::Maintainer,
+};
 
 async fn apply_patch(workspace: &Workspace, patch: &GitApplyPatch) -> Result<()> {
-    println!("Applying patch\n{}", patch.patch);
+    info!(patch = %patch.patch, "Applying patch.");
     workspace
         .spawn_with_stdin(
             "git",
@@ -17,26 +33,7 @@ async fn apply_patch(workspace: &Workspace, patch: &GitApplyPatch) -> Result<()>
     Ok(())
 }
 
-pub async fn run<Command>(
-    action: &AuthorizedAction<Command>,
-    patch: &GitApplyPatch,
-) -> Result<Box<dyn Display + Send>, (Status, anyhow::Error)>
-where
-    Command: TryFrom<RawCommand, Error = anyhow::Error>,
-{
-    let workspace = Workspace::create(action).await?;
-
-    async {
-        apply_patch(&workspace, patch).await?;
-        workspace.commit("apply diff").await?;
-        workspace.push().await?;
-        Ok::<(), anyhow::Error>(())
-    }
-    .await
-    .map_err(|err| (Status::InternalServerError, err))?;
-
-    Ok(Box::new(format!(
-        "Successfully ran mason apply in {:?}",
-        workspace
-    )))
+pub async fn run(workspace: &Workspace, patch: &GitApplyPatch) -> Result<()> {
+    apply_patch(workspace, patch).await?;
+    workspace.commit("apply diff").await
 }
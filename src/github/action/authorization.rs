@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{
+    config::{self, CommandPermission, RepoConfig},
+    github::{
+        client::GitHubClient,
+        data::{GitHubRepo, GitHubUser},
+    },
+};
+
+use super::parser::AuthorizedActionContext;
+
+/// How long looked up team memberships and repo permissions are trusted, so that revoked access
+/// takes effect soon while a burst of commands doesn't hit the API for each one.
+const LOOKUP_TTL: Duration = Duration::from_secs(60);
+
+type Lookups = HashMap<(String, String), (Instant, bool)>;
+
+lazy_static! {
+    /// Whether a user (by repo and login) was granted access through a lookup.
+    static ref LOOKUPS: Mutex<Lookups> = Mutex::new(HashMap::new());
+}
+
+/// Remembers the result of a lookup, forgetting the expired ones so the cache only holds the
+/// users seen within the last [`LOOKUP_TTL`].
+fn remember(lookups: &mut Lookups, key: (String, String), granted: bool) {
+    lookups.retain(|_, (looked_up_at, _)| looked_up_at.elapsed() < LOOKUP_TTL);
+    lookups.insert(key, (Instant::now(), granted));
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct AuthorizedUser(pub String);
+
+impl AuthorizedUser {
+    /// Authorizes the author of the comment in `context` to run `command`, which requires
+    /// `permission` unless the repo overrides it. Maintainers are the repo's maintainers, members of
+    /// its maintainer teams and collaborators with at least its maintainer permission. Otherwise,
+    /// the error tells why they were denied.
+    pub async fn authorize(
+        client: &GitHubClient,
+        context: &dyn AuthorizedActionContext,
+        command: &str,
+        permission: CommandPermission,
+    ) -> Result<Self> {
+        let user = &context.get_trigger().user;
+        let repo = context.get_repo();
+        let repo_config = config::get()
+            .repo(&repo.full_name)
+            .ok_or_else(|| anyhow!("{} is not a configured repo.", repo.full_name))?;
+        let permission = repo_config
+            .command_permissions
+            .get(command)
+            .copied()
+            .unwrap_or(permission);
+        if permission == CommandPermission::Anyone
+            || repo_config.maintainers.contains(&user.login)
+            || is_granted_by_lookup(client, user, repo, repo_config).await?
+        {
+            return Ok(AuthorizedUser(user.login.to_owned()));
+        }
+        if permission == CommandPermission::PullRequestAuthor
+            && is_pull_request_author(client, context, user).await?
+        {
+            info!(user = %user.login, command, "Authorized as author of the pull request.");
+            return Ok(AuthorizedUser(user.login.to_owned()));
+        }
+        Err(anyhow!(denial(
+            user,
+            repo,
+            repo_config,
+            command,
+            permission
+        )))
+    }
+}
+
+async fn is_pull_request_author(
+    client: &GitHubClient,
+    context: &dyn AuthorizedActionContext,
+    user: &GitHubUser,
+) -> Result<bool> {
+    Ok(context
+        .get_pull_request(client)
+        .await
+        .map_err(|err| anyhow!("Failed to fetch the pull request: {}", err))?
+        .is_some_and(|pull_request| pull_request.user.id == user.id))
+}
+
+async fn is_granted_by_lookup(
+    client: &GitHubClient,
+    user: &GitHubUser,
+    repo: &GitHubRepo,
+    repo_config: &RepoConfig,
+) -> Result<bool> {
+    if repo_config.maintainer_teams.is_empty() && repo_config.maintainer_permission.is_none() {
+        return Ok(false);
+    }
+    let key = (repo.full_name.to_string(), user.login.to_owned());
+    if let Some((looked_up_at, granted)) = LOOKUPS.lock().await.get(&key) {
+        if looked_up_at.elapsed() < LOOKUP_TTL {
+            return Ok(*granted);
+        }
+    }
+
+    let granted = lookup(client, user, repo, repo_config)
+        .await
+        .inspect_err(|err| error!(error = %err, user = %user.login, "Failed to look up access."))
+        .map_err(|err| anyhow!("Failed to verify the access of {}: {}", user.login, err))?;
+    remember(&mut *LOOKUPS.lock().await, key, granted);
+    Ok(granted)
+}
+
+async fn lookup(
+    client: &GitHubClient,
+    user: &GitHubUser,
+    repo: &GitHubRepo,
+    repo_config: &RepoConfig,
+) -> Result<bool> {
+    for team in &repo_config.maintainer_teams {
+        let Some((org, slug)) = team.split_once('/') else {
+            continue;
+        };
+        if client
+            .is_team_member(&repo.full_name, org, slug, &user.login)
+            .await?
+        {
+            info!(user = %user.login, team, "Authorized through team membership.");
+            return Ok(true);
+        }
+    }
+    if let Some(minimum) = repo_config.maintainer_permission {
+        let permission = client
+            .get_collaborator_permission(&repo.full_name, &user.login)
+            .await?;
+        if permission >= minimum {
+            info!(user = %user.login, %permission, "Authorized through repo permission.");
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Explains who may run `command`, for the reply to a denied user.
+fn denial(
+    user: &GitHubUser,
+    repo: &GitHubRepo,
+    repo_config: &RepoConfig,
+    command: &str,
+    permission: CommandPermission,
+) -> String {
+    let mut allowed = vec![];
+    if !repo_config.maintainers.is_empty() {
+        allowed.push(format!(
+            "maintainers ({})",
+            repo_config.maintainers.join(", ")
+        ));
+    }
+    if !repo_config.maintainer_teams.is_empty() {
+        allowed.push(format!(
+            "members of {}",
+            repo_config.maintainer_teams.join(", ")
+        ));
+    }
+    if let Some(permission) = repo_config.maintainer_permission {
+        allowed.push(format!("collaborators with {} permission", permission));
+    }
+    if permission == CommandPermission::PullRequestAuthor {
+        allowed.push("the author of the pull request".to_owned());
+    }
+    let mut reason = format!(
+        "{} is not allowed to run {} in {}.",
+        user.login, command, repo.full_name
+    );
+    if !allowed.is_empty() {
+        reason.push_str(&format!(" It can be run by {}.", allowed.join(" or ")));
+    }
+    reason
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Instant};
+
+    use serde_json::json;
+
+    use super::{denial, remember, LOOKUP_TTL};
+    use crate::config::{CommandPermission, RepoConfig};
+
+    #[test]
+    fn it_should_explain_denials() {
+        let user = serde_json::from_value(json!({ "id": 1, "login": "octocat" })).unwrap();
+        let repo =
+            serde_json::from_value(json!({ "id": 1, "full_name": "mason-org/mason-registry" }))
+                .unwrap();
+        let repo_config = RepoConfig {
+            maintainers: vec!["williamboman".to_owned()],
+            maintainer_teams: vec!["mason-org/triage".to_owned()],
+            maintainer_permission: Some(crate::github::data::GitHubRepoPermission::Maintain),
+            ..Default::default()
+        };
+        assert_eq!(
+            denial(
+                &user,
+                &repo,
+                &repo_config,
+                "apply",
+                CommandPermission::Maintainer
+            ),
+            "octocat is not allowed to run apply in mason-org/mason-registry. It can be run by maintainers (williamboman) or members of mason-org/triage or collaborators with maintain permission."
+        );
+        assert_eq!(
+            denial(
+                &user,
+                &repo,
+                &RepoConfig::default(),
+                "fixup",
+                CommandPermission::PullRequestAuthor
+            ),
+            "octocat is not allowed to run fixup in mason-org/mason-registry. It can be run by the author of the pull request."
+        );
+        assert_eq!(
+            denial(
+                &user,
+                &repo,
+                &RepoConfig::default(),
+                "apply",
+                CommandPermission::Maintainer
+            ),
+            "octocat is not allowed to run apply in mason-org/mason-registry."
+        );
+    }
+
+    #[test]
+    fn it_should_forget_expired_lookups() {
+        let key = |login: &str| ("mason-org/mason-registry".to_owned(), login.to_owned());
+        let mut lookups = HashMap::from([(
+            key("octocat"),
+            (Instant::now().checked_sub(LOOKUP_TTL * 2).unwrap(), true),
+        )]);
+        remember(&mut lookups, key("williamboman"), true);
+        remember(&mut lookups, key("hubot"), false);
+        assert_eq!(lookups.len(), 2);
+        assert!(!lookups.contains_key(&key("octocat")));
+    }
+}
@@ -13,7 +13,7 @@ impl TryFrom<String> for GitApplyPatch {
             .trim_start_matches(char::is_whitespace)
             .replace("\r", "");
         let lines = massaged_value.split_inclusive("\n");
-        let mut lines_iter = lines.clone().into_iter();
+        let mut lines_iter = lines.clone();
         let header = lines_iter.next().ok_or_else(|| anyhow!("No header."))?;
         if !header.starts_with("```diff") {
             bail!("Not a diff.")
@@ -1,50 +1,413 @@
-use crate::github::{client, data::GitHubReaction};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use self::parser::{AuthorizedAction, AuthorizedActionExecutor, RawCommand};
+use crate::{
+    config::{self, CommandPermission},
+    github::{client::GitHubClient, data::GitHubReaction, webhook::registry::Disposition},
+    jobs::JobContext,
+    metrics::{self, CommandResult},
+    store::ActionRecord,
+    workspace::Workspace,
+};
 
-use super::data::{GitHubIssueCommentEvent, GitHubIssueCommentEventAction};
+use self::{
+    parser::{
+        AuthorizedAction, AuthorizedActionContext, AuthorizedActionExecutor, InvalidAction,
+        RawCommand, RequestedAction, Syntax,
+    },
+    spec::{ArgSpec, CommandSpec},
+};
+
+use super::data::{
+    GitHubIssueCommentEventAction, GitHubPullRequestReviewAction,
+    GitHubPullRequestReviewCommentAction, GitHubWebhook,
+};
+use anyhow::anyhow;
 use rocket::http::Status;
+use tracing::{error, info, Span};
 
 pub mod apply;
+pub mod authorization;
 pub mod common;
 pub mod merge_base;
 pub mod parser;
+pub mod spec;
+
+/// How long a user who was told why their commands were rejected on an issue or pull request only
+/// gets reactions to further rejections there, so that the bot can't be made to flood a thread.
+const REPLY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+type Replies = HashMap<(String, u64, String), Instant>;
+
+lazy_static! {
+    /// When a user (by repo, issue or pull request, and login) was last told why their commands
+    /// were rejected.
+    static ref REPLIES: Mutex<Replies> = Mutex::new(HashMap::new());
+}
+
+static CANCEL: CommandSpec = CommandSpec {
+    name: "cancel",
+    aliases: &[],
+    args: &[],
+    flags: &[],
+    description: "Cancels the commands queued or running for this pull request.",
+    permission: CommandPermission::Maintainer,
+};
+
+static HELP: CommandSpec = CommandSpec {
+    name: "help",
+    aliases: &[],
+    args: &[ArgSpec {
+        name: "command",
+        description: "The command to describe in detail.",
+        required: false,
+        block: false,
+    }],
+    flags: &[],
+    description: "Lists the available commands, or describes one.",
+    permission: CommandPermission::Anyone,
+};
+
+/// Commands that are understood regardless of the repo's handler, wrapping the handler's own.
+enum BuiltinCommand<Command> {
+    Cancel,
+    Help(Option<String>),
+    Other(Command),
+}
+
+impl<Command> BuiltinCommand<Command>
+where
+    Command: AuthorizedActionExecutor,
+{
+    fn specs() -> Vec<&'static CommandSpec> {
+        let mut specs = Command::specs().to_vec();
+        specs.extend([&CANCEL, &HELP]);
+        specs
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        match self {
+            Self::Cancel => &CANCEL,
+            Self::Help(_) => &HELP,
+            Self::Other(command) => command.spec(),
+        }
+    }
+
+    fn is_builtin(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
 
-pub async fn handle_issue_comment<Command>(event: GitHubIssueCommentEvent) -> Status
+impl<Command> TryFrom<RawCommand> for BuiltinCommand<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
+{
+    type Error = anyhow::Error;
+
+    fn try_from(value: RawCommand) -> Result<Self, Self::Error> {
+        match value.raw_command.as_str() {
+            name if CANCEL.matches(name) => {
+                CANCEL.parse(value)?;
+                Ok(Self::Cancel)
+            }
+            name if HELP.matches(name) => {
+                let args = HELP.parse(value)?;
+                Ok(Self::Help(
+                    args.arg("command")
+                        .map(|command| command.trim_start_matches('/').to_owned()),
+                ))
+            }
+            _ => Ok(Self::Other(value.try_into()?)),
+        }
+    }
+}
+
+/// Extracts the action requested by a webhook, if it's an event that can carry commands and
+/// addresses any to the bot.
+fn requested_action<Command>(
+    webhook: GitHubWebhook,
+    specs: &[&'static CommandSpec],
+) -> Option<Result<RequestedAction<Command>, InvalidAction>>
+where
+    Command: TryFrom<RawCommand, Error = anyhow::Error>,
+{
+    match webhook {
+        GitHubWebhook::IssueComment(event) => match event.action {
+            GitHubIssueCommentEventAction::Created => RequestedAction::parse(event, specs),
+            GitHubIssueCommentEventAction::Edited | GitHubIssueCommentEventAction::Deleted => None,
+        },
+        GitHubWebhook::PullRequestReview(event) => match event.action {
+            GitHubPullRequestReviewAction::Submitted => RequestedAction::parse(event, specs),
+            GitHubPullRequestReviewAction::Edited | GitHubPullRequestReviewAction::Dismissed => {
+                None
+            }
+        },
+        GitHubWebhook::PullRequestReviewComment(event) => match event.action {
+            GitHubPullRequestReviewCommentAction::Created => RequestedAction::parse(event, specs),
+            GitHubPullRequestReviewCommentAction::Edited
+            | GitHubPullRequestReviewCommentAction::Deleted => None,
+        },
+        GitHubWebhook::Issues(_) | GitHubWebhook::PullRequest(_) | GitHubWebhook::CheckRun(_) => {
+            None
+        }
+    }
+}
+
+/// Decides whether a webhook requests an authorized command, in which case it's to be run as a job.
+/// Builtin commands are handled right away.
+pub async fn accept<Command>(client: &GitHubClient, webhook: GitHubWebhook) -> Disposition
+where
     Command: AuthorizedActionExecutor,
 {
-    let repo = event.repository.clone();
-    let comment = event.comment.clone();
-    match event.action {
-        GitHubIssueCommentEventAction::Created => match event.try_into() {
-            Ok(action @ AuthorizedAction::<Command> { .. }) => {
-                match Command::execute(action).await {
-                    Ok(result) => {
-                        println!("{}", result);
-                        Status::NoContent
-                    }
-                    Err((status, err)) => {
-                        let _ = client::unminimize_comment(&comment).await;
-                        let _ = client::create_issue_comment_reaction(
-                            &repo,
-                            &comment,
-                            &GitHubReaction::MinusOne,
-                        )
-                        .await;
-                        eprintln!("ERROR: {:?}", err);
-                        status
-                    }
+    let number = webhook.issue_number();
+    let requested = match requested_action::<BuiltinCommand<Command>>(
+        webhook,
+        &BuiltinCommand::<Command>::specs(),
+    ) {
+        Some(Ok(requested)) => requested,
+        Some(Err(invalid)) => {
+            info!(error = %invalid.error, "Invalid command in comment.");
+            reply_rejected(client, invalid.context.as_ref(), number, &invalid.error).await;
+            return Disposition::Handled(Status::NoContent);
+        }
+        None => return Disposition::Handled(Status::NoContent),
+    };
+    Span::current().record("command", requested.names().as_str());
+    if requested.actions.len() > 1
+        && requested
+            .actions
+            .iter()
+            .any(|action| action.command.is_builtin())
+    {
+        let err = anyhow!("/cancel and /help can't be combined with other commands.");
+        info!(error = %err, "Invalid command in comment.");
+        reply_rejected(client, requested.context.as_ref(), number, &err).await;
+        return Disposition::Handled(Status::NoContent);
+    }
+    let action = match requested.authorize(client, BuiltinCommand::spec).await {
+        Ok(authorized_by) => requested.authorized(authorized_by),
+        Err(err) => {
+            info!(error = %err, "Denied command.");
+            reply_rejected(client, requested.context.as_ref(), number, &err).await;
+            return Disposition::Handled(Status::NoContent);
+        }
+    };
+    match &action.actions[0].command {
+        BuiltinCommand::Cancel => {
+            if let Err(err) = client
+                .create_comment_reaction(
+                    action.context.get_repo(),
+                    action.context.get_trigger_kind(),
+                    action.context.get_trigger(),
+                    &GitHubReaction::PlusOne,
+                )
+                .await
+            {
+                error!(error = ?err, "Failed to react to cancel command.");
+            }
+            Disposition::Cancel
+        }
+        BuiltinCommand::Help(command) => {
+            let repo = action.context.get_repo();
+            let specs = BuiltinCommand::<Command>::specs();
+            let help = spec::help(
+                &specs,
+                Syntax::for_repo(repo, &specs).bot(),
+                config::get().repo(&repo.full_name),
+                command.as_deref(),
+            );
+            if let Some(number) = number {
+                if let Err(err) = client.create_issue_comment(repo, number, &help).await {
+                    error!(error = ?err, "Failed to reply to help command.");
                 }
             }
-            Err(err) => {
-                println!("Failed to parse action from comment: {:?}", err);
-                Status::NoContent
+            Disposition::Handled(Status::NoContent)
+        }
+        BuiltinCommand::Other(_) => Disposition::Enqueue,
+    }
+}
+
+/// Runs the commands requested by a previously accepted webhook, in order and on one shared
+/// workspace. The workspace is pushed once every command succeeded.
+pub async fn run<Command>(
+    client: &GitHubClient,
+    webhook: GitHubWebhook,
+    job: JobContext,
+) -> anyhow::Result<Box<dyn Display + Send>>
+where
+    Command: AuthorizedActionExecutor + Debug,
+{
+    let requested = requested_action::<Command>(webhook, Command::specs())
+        .ok_or_else(|| anyhow!("Webhook doesn't request a command."))?
+        .map_err(|invalid| invalid.error)?;
+    Span::current().record("command", requested.names().as_str());
+    // Access may have been revoked while the job was queued.
+    let authorized_by = requested.authorize(client, Command::spec).await?;
+    let mut action = requested.authorized(authorized_by);
+    record_actions(client, &action, &job).await;
+    let cancellation = job.cancellation.clone();
+    action.job = Some(job);
+
+    let mut succeeded = 0;
+    let result = async {
+        let workspace = Workspace::create(client, &action)
+            .await
+            .map_err(|(_, err)| err)?;
+        for requested in &action.actions {
+            info!(command = %requested.name, "Running command.");
+            requested.command.execute(&workspace).await?;
+            succeeded += 1;
+        }
+        workspace.push().await?;
+        Ok::<_, anyhow::Error>(workspace)
+    }
+    .await;
+
+    let names = action
+        .actions
+        .iter()
+        .map(|action| action.command.spec().name)
+        .collect::<Vec<_>>();
+    match result {
+        Ok(workspace) => {
+            for name in &names {
+                metrics::command(name, CommandResult::Succeeded);
             }
-        },
-        GitHubIssueCommentEventAction::Edited | GitHubIssueCommentEventAction::Deleted => {
-            Status::NoContent
+            Ok(Box::new(format!(
+                "Successfully ran {} in {:?}",
+                action.names(),
+                workspace
+            )))
+        }
+        Err(err) => {
+            // Commands that ran before the failing one count as succeeded, though nothing was
+            // pushed. A failed push counts against the last command.
+            let failed = succeeded.min(names.len() - 1);
+            for name in &names[..failed] {
+                metrics::command(name, CommandResult::Succeeded);
+            }
+            if cancellation.is_cancelled() {
+                metrics::command(names[failed], CommandResult::Cancelled);
+            } else {
+                metrics::command(names[failed], CommandResult::Failed);
+            }
+            let repo = action.context.get_repo();
+            let comment = action.context.get_trigger();
+            let kind = action.context.get_trigger_kind();
+            if kind.is_minimizable() {
+                let _ = client.unminimize_comment(repo, comment).await;
+            }
+            let _ = client
+                .create_comment_reaction(repo, kind, comment, &GitHubReaction::MinusOne)
+                .await;
+            Err(err.context(format!("Failed to run {}.", names[failed])))
         }
     }
 }
+
+/// Tells the author of a comment why their commands won't be run. The comment is reacted to, and
+/// replied to unless its author was already replied to in the thread within [`REPLY_INTERVAL`].
+async fn reply_rejected(
+    client: &GitHubClient,
+    context: &dyn AuthorizedActionContext,
+    number: Option<u64>,
+    err: &anyhow::Error,
+) {
+    let repo = context.get_repo();
+    let comment = context.get_trigger();
+    let _ = client
+        .create_comment_reaction(
+            repo,
+            context.get_trigger_kind(),
+            comment,
+            &GitHubReaction::MinusOne,
+        )
+        .await;
+    let Some(number) = number else {
+        return;
+    };
+    let key = (
+        repo.full_name.to_string(),
+        number,
+        comment.user.login.to_owned(),
+    );
+    if !should_reply(&mut REPLIES.lock().unwrap(), key) {
+        info!("Recently replied to rejected commands, only reacting.");
+        return;
+    }
+    let _ = client
+        .create_issue_comment(repo, number, &format!("@{} {}", comment.user.login, err))
+        .await;
+}
+
+/// Whether to reply to `key` now, in which case the reply is remembered. Replies older than
+/// [`REPLY_INTERVAL`] are forgotten.
+fn should_reply(replies: &mut Replies, key: (String, u64, String)) -> bool {
+    replies.retain(|_, replied_at| replied_at.elapsed() < REPLY_INTERVAL);
+    if replies.contains_key(&key) {
+        return false;
+    }
+    replies.insert(key, Instant::now());
+    true
+}
+
+async fn record_actions<Command>(
+    client: &GitHubClient,
+    action: &AuthorizedAction<Command>,
+    job: &JobContext,
+) where
+    Command: AuthorizedActionExecutor + Debug,
+{
+    let pull_request = match action.context.get_pull_request(client).await {
+        Ok(pull_request) => pull_request,
+        Err(err) => {
+            error!(error = ?err, "Failed to fetch pull request of job.");
+            None
+        }
+    };
+    for requested in &action.actions {
+        let record = ActionRecord {
+            job_id: &job.id,
+            command: requested.command.spec().name.to_owned(),
+            details: format!("{:?}", requested.command),
+            authorized_by: &action.authorized_by.0,
+            repo: action.context.get_repo().full_name.to_string(),
+            pull_request: pull_request.as_ref().map(|pr| pr.number),
+            head_sha: pull_request.as_ref().map(|pr| pr.head.sha.as_str()),
+        };
+        if let Err(err) = job.store.record_action(&record) {
+            error!(error = ?err, "Failed to record action of job.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Instant};
+
+    use super::{should_reply, REPLY_INTERVAL};
+
+    #[test]
+    fn it_should_reply_to_rejected_commands_once_per_interval() {
+        let key = |number: u64, login: &str| {
+            (
+                "mason-org/mason-registry".to_owned(),
+                number,
+                login.to_owned(),
+            )
+        };
+        let mut replies = HashMap::from([(
+            key(1, "hubot"),
+            Instant::now().checked_sub(REPLY_INTERVAL * 2).unwrap(),
+        )]);
+        assert!(should_reply(&mut replies, key(1, "octocat")));
+        assert!(!should_reply(&mut replies, key(1, "octocat")));
+        assert!(should_reply(&mut replies, key(2, "octocat")));
+        assert!(should_reply(&mut replies, key(1, "hubot")));
+        assert_eq!(replies.len(), 3);
+    }
+}
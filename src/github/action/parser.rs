@@ -1,204 +1,388 @@
-use anyhow::{anyhow, bail, Result};
-use rocket::http::Status;
-use std::{
-    fmt::{Debug, Display},
-    str::FromStr,
+use anyhow::{anyhow, Result};
+use std::{fmt::Debug, sync::OnceLock};
+use tracing::{info, warn};
+
+use crate::{
+    config,
+    github::{client::GitHubClient, data::*},
+    jobs::JobContext,
+    workspace::Workspace,
 };
 
-use crate::github::{client, data::*};
+use super::{
+    authorization::AuthorizedUser,
+    spec::{self, CommandSpec},
+};
 
-#[derive(Debug)]
-pub struct Actionee(pub String);
+/// The name the bot is mentioned with, as resolved from its credentials on startup.
+static IDENTITY: OnceLock<String> = OnceLock::new();
 
-impl FromStr for Actionee {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.get(..1) {
-            Some("@") => match s.get(1..) {
-                Some(user @ "williambotman") => Ok(Actionee(user.to_owned())),
-                Some(user) => bail!("{} is not an allowed user.", user),
-                None => bail!("{} is not a valid mention.", s),
-            },
-            Some(_) | None => bail!("{} is not a valid mention.", s),
+/// Resolves the name the bot is mentioned with from its credentials, falling back to
+/// `github.login` if they can't be resolved.
+pub async fn resolve_identity(client: &GitHubClient) {
+    match client.identity().await {
+        Ok(identity) => {
+            info!(identity, "Resolved the bot's identity.");
+            let _ = IDENTITY.set(identity);
+        }
+        Err(err) => {
+            warn!(error = %err, "Failed to resolve the bot's identity, using github.login.")
         }
     }
 }
 
+/// The name a command was addressed to, without the `@`.
+#[allow(dead_code)]
 #[derive(Debug)]
-pub struct AuthorizedUser(pub String);
+pub struct Actionee(pub String);
 
-impl FromStr for AuthorizedUser {
-    type Err = anyhow::Error;
+/// How commands are invoked in a repo's comments.
+#[derive(Debug)]
+pub struct Syntax {
+    /// Names the bot answers to, without the `@`. The first one is the bot's own.
+    mentions: Vec<String>,
+    /// The commands understood in the repo.
+    commands: Vec<&'static CommandSpec>,
+    /// Whether commands are also accepted without a mention.
+    bare_commands: bool,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            user @ "williamboman" => Ok(AuthorizedUser(user.to_owned())),
-            user => bail!("{} is not an allowed user.", user),
+impl Syntax {
+    /// The name the bot is mentioned with.
+    pub fn bot(&self) -> &str {
+        &self.mentions[0]
+    }
+
+    /// The syntax of `repo`, whose handler understands the commands of `specs`.
+    pub fn for_repo(repo: &GitHubRepo, specs: &[&'static CommandSpec]) -> Self {
+        let config = config::get();
+        let mut mentions = vec![];
+        for name in IDENTITY
+            .get()
+            .into_iter()
+            .chain(std::iter::once(&config.github.login))
+            .chain(&config.github.aliases)
+        {
+            let name = name.strip_prefix('@').unwrap_or(name);
+            if !mentions
+                .iter()
+                .any(|mention: &String| mention.eq_ignore_ascii_case(name))
+            {
+                mentions.push(name.to_owned());
+            }
+        }
+        Self {
+            mentions,
+            commands: specs.to_vec(),
+            bare_commands: config
+                .repo(&repo.full_name)
+                .is_some_and(|repo_config| repo_config.bare_commands),
         }
     }
-}
 
-impl TryFrom<&GitHubUser> for AuthorizedUser {
-    type Error = anyhow::Error;
+    /// Finds the commands invoked in the Markdown comment `body`, in order. Quoted lines and fenced
+    /// code are skipped, except for a fenced block right after a command line, which becomes the
+    /// arguments of the line's last command (e.g. the diff of `/apply`).
+    fn invocations(&self, body: &str) -> Vec<(Actionee, RawCommand)> {
+        let lines = body.lines().collect::<Vec<_>>();
+        let mut invocations = vec![];
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i].trim();
+            i += 1;
+            if is_fence(line) {
+                i += fenced_block(&lines[i - 1..]).1 - 1;
+                continue;
+            }
+            if line.starts_with('>') {
+                continue;
+            }
+            let Some((actionee, mut commands)) = self.command_line(line) else {
+                continue;
+            };
+            let blank_lines = lines[i..]
+                .iter()
+                .take_while(|line| line.trim().is_empty())
+                .count();
+            if lines
+                .get(i + blank_lines)
+                .is_some_and(|line| is_fence(line.trim()))
+            {
+                let (block, length) = fenced_block(&lines[i + blank_lines..]);
+                if let Some(command) = commands.last_mut() {
+                    command.block = Some(block);
+                }
+                i += blank_lines + length;
+            }
+            invocations.extend(
+                commands
+                    .into_iter()
+                    .map(|command| (Actionee(actionee.0.clone()), command)),
+            );
+        }
+        invocations
+    }
+
+    /// Finds the commands on a line, following a mention of the bot or, if bare commands are
+    /// accepted, from the start of the line. Each command comes with the words following it, up
+    /// to the next command. A `/word` that isn't a known command, as in "/shrug" or "/tmp", is
+    /// prose.
+    fn command_line(&self, line: &str) -> Option<(Actionee, Vec<RawCommand>)> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (actionee, start) =
+            if self.bare_commands && self.known_command(words.first()?).is_some() {
+                (Actionee(self.bot().to_owned()), 0)
+            } else {
+                words.windows(2).enumerate().find_map(|(i, pair)| {
+                    let mention = self.mentioned(pair[0])?;
+                    self.known_command(pair[1])?;
+                    Some((Actionee(mention.to_owned()), i + 1))
+                })?
+            };
+        let mut commands: Vec<RawCommand> = vec![];
+        for word in &words[start..] {
+            match (self.known_command(word), commands.last_mut()) {
+                (Some(raw_command), _) => commands.push(RawCommand {
+                    raw_command,
+                    words: vec![],
+                    block: None,
+                }),
+                (None, Some(command)) => command.words.push((*word).to_owned()),
+                (None, None) => {}
+            }
+        }
+        Some((actionee, commands))
+    }
 
-    fn try_from(value: &GitHubUser) -> Result<Self, Self::Error> {
-        value.login.parse()
+    /// The name of the command `word` invokes, if it's a `/command` understood in the repo.
+    fn known_command(&self, word: &str) -> Option<String> {
+        command_name(word).filter(|name| spec::find(&self.commands, name).is_some())
+    }
+
+    fn mentioned(&self, word: &str) -> Option<&str> {
+        let name = word.strip_prefix('@')?.trim_end_matches(PUNCTUATION);
+        self.mentions
+            .iter()
+            .find(|mention| mention.eq_ignore_ascii_case(name))
+            .map(String::as_str)
     }
 }
 
-#[derive(Debug)]
-pub struct RawCommand {
-    pub raw_command: String,
-    pub raw_arguments: Option<String>,
+/// Trailing punctuation that doesn't belong to mentions and commands, as in "@bot /fixup, please".
+const PUNCTUATION: &[char] = &[',', '.', ':', ';', '!', '?'];
+
+/// The name of the command `word` invokes, if it's a `/command`.
+fn command_name(word: &str) -> Option<String> {
+    let name = word.strip_prefix('/')?.trim_end_matches(PUNCTUATION);
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .then(|| name.to_owned())
+}
+
+fn is_fence(line: &str) -> bool {
+    line.starts_with("```") || line.starts_with("~~~")
 }
 
-impl FromStr for RawCommand {
-    type Err = anyhow::Error;
-
-    fn from_str(command_body: &str) -> Result<Self, Self::Err> {
-        match command_body.get(..1) {
-            Some("/") => match command_body.get(1..) {
-                Some(body) => match body.split_once(char::is_whitespace) {
-                    Some((raw_command, raw_arguments)) => Ok(Self {
-                        raw_command: (*raw_command).to_owned(),
-                        raw_arguments: Some((*raw_arguments).to_owned()),
-                    }),
-                    None => Ok(Self {
-                        raw_command: (&command_body[1..]).to_owned(),
-                        raw_arguments: None,
-                    }),
-                },
-
-                None => bail!("{} is not a valid command.", command_body),
-            },
-            Some(_) | None => bail!("{} is not a valid command", command_body),
+/// Reads the fenced block `lines` start with, returning it along with how many lines it spans.
+/// Unterminated blocks run to the end of the comment, as in Markdown.
+fn fenced_block(lines: &[&str]) -> (String, usize) {
+    let mut block = vec![lines[0].trim()];
+    for line in &lines[1..] {
+        if is_fence(line.trim()) {
+            block.push(line.trim());
+            return (block.join("\n"), block.len());
         }
+        block.push(line);
     }
+    (block.join("\n"), block.len())
+}
+
+/// A command as written in a comment, to be validated against its [`CommandSpec`].
+#[derive(Debug)]
+pub struct RawCommand {
+    pub raw_command: String,
+    /// The words following the command on its line, up to the next command.
+    pub words: Vec<String>,
+    /// The fenced block following the command's line, if it's the line's last command.
+    pub block: Option<String>,
 }
 
 #[async_trait]
 pub trait AuthorizedActionContext: Sync + Send + Debug {
-    async fn get_pull_request(&self) -> Result<Option<GitHubPullRequest>> {
+    async fn get_pull_request(&self, _client: &GitHubClient) -> Result<Option<GitHubPullRequest>> {
         Ok(None)
     }
 
     fn get_repo(&self) -> &GitHubRepo;
 
     fn get_trigger(&self) -> &GitHubComment;
+
+    fn get_trigger_kind(&self) -> GitHubCommentKind;
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Action<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
     pub actionee: Actionee,
+    /// The name the command was invoked as, e.g. `fixup`.
+    pub name: String,
     pub command: Command,
 }
 
-impl<Command> FromStr for Action<Command>
+impl<Command> Action<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    type Err = anyhow::Error;
+    /// Parses every command invoked in the comment `body`, which may be none.
+    pub fn parse_all(body: &str, syntax: &Syntax) -> Result<Vec<Self>> {
+        let actions = syntax
+            .invocations(body)
+            .into_iter()
+            .map(|(actionee, raw_command)| {
+                Ok(Self {
+                    actionee,
+                    name: raw_command.raw_command.clone(),
+                    command: raw_command.try_into()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(actions)
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self> {
-        let (mention, command) = s
-            .split_once(" ")
-            .ok_or_else(|| anyhow!("{} is not valid action syntax.", s))?;
+#[async_trait]
+pub trait AuthorizedActionExecutor: TryFrom<RawCommand, Error = anyhow::Error> + Sync {
+    /// The commands understood, as listed by `/help`.
+    fn specs() -> &'static [&'static CommandSpec];
 
-        let actionee = mention.parse()?;
-        let command = command.parse::<RawCommand>()?.try_into()?;
+    /// The spec the command was parsed with.
+    fn spec(&self) -> &'static CommandSpec;
 
-        Ok(Self { actionee, command })
-    }
+    /// Runs the command in the pull request's workspace. The workspace is shared by every command
+    /// of a comment and only pushed once they all succeeded.
+    async fn execute(&self, workspace: &Workspace) -> Result<()>;
+}
+
+/// A comment addressing commands to the bot that aren't valid, e.g. missing arguments.
+#[derive(Debug)]
+pub struct InvalidAction {
+    pub context: Box<dyn AuthorizedActionContext>,
+    pub error: anyhow::Error,
 }
 
-impl<Command> TryFrom<&GitHubPullRequestReviewComment> for Action<Command>
+/// The actions requested in a comment, yet to be authorized.
+#[derive(Debug)]
+pub struct RequestedAction<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &GitHubPullRequestReviewComment) -> Result<Self, Self::Error> {
-        value
-            .comment
-            .body
-            .as_ref()
-            .ok_or_else(|| anyhow!("Body is empty."))?
-            .parse()
-    }
+    pub actions: Vec<Action<Command>>,
+    pub context: Box<dyn AuthorizedActionContext>,
 }
 
-impl<Command> TryFrom<&GitHubPullRequestReview> for Action<Command>
+impl<Command> RequestedAction<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    type Error = anyhow::Error;
+    /// Parses the actions requested in the comment of `event`, if it addresses any to the bot.
+    /// `specs` are the commands understood in the repo.
+    pub fn parse<Event>(
+        event: Event,
+        specs: &[&'static CommandSpec],
+    ) -> Option<Result<Self, InvalidAction>>
+    where
+        Event: AuthorizedActionContext + 'static,
+    {
+        let body = event.get_trigger().body.as_ref()?;
+        match Action::parse_all(body, &Syntax::for_repo(event.get_repo(), specs)) {
+            Ok(actions) if actions.is_empty() => None,
+            Ok(actions) => Some(Ok(Self {
+                actions,
+                context: Box::new(event),
+            })),
+            Err(error) => Some(Err(InvalidAction {
+                context: Box::new(event),
+                error,
+            })),
+        }
+    }
 
-    fn try_from(value: &GitHubPullRequestReview) -> Result<Self, Self::Error> {
-        value
-            .review
-            .body
-            .as_ref()
-            .ok_or_else(|| anyhow!("Body is empty."))?
-            .parse()
+    /// The names of the requested commands, e.g. `merge-base, fixup`.
+    pub fn names(&self) -> String {
+        names(&self.actions)
     }
-}
 
-#[async_trait]
-pub trait AuthorizedActionExecutor: TryFrom<RawCommand, Error = anyhow::Error> {
-    async fn execute(
-        action: AuthorizedAction<Self>,
-    ) -> Result<Box<dyn Display + Send>, (Status, anyhow::Error)>;
+    /// Checks whether the author of the comment may run every requested action.
+    pub async fn authorize(
+        &self,
+        client: &GitHubClient,
+        spec: impl Fn(&Command) -> &'static CommandSpec,
+    ) -> Result<AuthorizedUser> {
+        let mut authorized_by = None;
+        for action in &self.actions {
+            let spec = spec(&action.command);
+            authorized_by = Some(
+                AuthorizedUser::authorize(
+                    client,
+                    self.context.as_ref(),
+                    spec.name,
+                    spec.permission,
+                )
+                .await?,
+            );
+        }
+        authorized_by.ok_or_else(|| anyhow!("No command to authorize."))
+    }
+
+    pub fn authorized(self, authorized_by: AuthorizedUser) -> AuthorizedAction<Command> {
+        AuthorizedAction {
+            actions: self.actions,
+            context: self.context,
+            authorized_by,
+            job: None,
+        }
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct AuthorizedAction<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    pub action: Action<Command>,
+    /// The commands of the comment, run in order.
+    pub actions: Vec<Action<Command>>,
     pub context: Box<dyn AuthorizedActionContext>,
     pub authorized_by: AuthorizedUser,
+    /// The job the action is run as, once it has been dequeued.
+    pub job: Option<JobContext>,
 }
 
-impl<Command> TryFrom<GitHubPullRequestReviewComment> for AuthorizedAction<Command>
+impl<Command> AuthorizedAction<Command>
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    type Error = anyhow::Error;
-
-    fn try_from(value: GitHubPullRequestReviewComment) -> Result<Self, Self::Error> {
-        let authorized_by = (&value.comment.user).try_into()?;
-        Ok(Self {
-            action: (&value).try_into()?,
-            context: Box::new(value),
-            authorized_by,
-        })
+    pub fn names(&self) -> String {
+        names(&self.actions)
     }
 }
 
-impl<Command> TryFrom<GitHubPullRequestReview> for AuthorizedAction<Command>
+fn names<Command>(actions: &[Action<Command>]) -> String
 where
     Command: TryFrom<RawCommand, Error = anyhow::Error>,
 {
-    type Error = anyhow::Error;
-
-    fn try_from(value: GitHubPullRequestReview) -> Result<Self, Self::Error> {
-        let authorized_by = (&value.review.user).try_into()?;
-        Ok(Self {
-            action: (&value).try_into()?,
-            context: Box::new(value),
-            authorized_by,
-        })
-    }
+    actions
+        .iter()
+        .map(|action| action.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[async_trait]
 impl AuthorizedActionContext for GitHubPullRequestReviewComment {
-    async fn get_pull_request(&self) -> Result<Option<GitHubPullRequest>> {
+    async fn get_pull_request(&self, _client: &GitHubClient) -> Result<Option<GitHubPullRequest>> {
         Ok(Some(self.pull_request.clone()))
     }
 
@@ -206,14 +390,18 @@ impl AuthorizedActionContext for GitHubPullRequestReviewComment {
         &self.comment
     }
 
+    fn get_trigger_kind(&self) -> GitHubCommentKind {
+        GitHubCommentKind::PullRequestReviewComment
+    }
+
     fn get_repo(&self) -> &GitHubRepo {
-        &self.pull_request.base.repo
+        &self.repository
     }
 }
 
 #[async_trait]
 impl AuthorizedActionContext for GitHubPullRequestReview {
-    async fn get_pull_request(&self) -> Result<Option<GitHubPullRequest>> {
+    async fn get_pull_request(&self, _client: &GitHubClient) -> Result<Option<GitHubPullRequest>> {
         Ok(Some(self.pull_request.clone()))
     }
 
@@ -221,48 +409,20 @@ impl AuthorizedActionContext for GitHubPullRequestReview {
         &self.review
     }
 
-    fn get_repo(&self) -> &GitHubRepo {
-        &self.pull_request.base.repo
+    fn get_trigger_kind(&self) -> GitHubCommentKind {
+        GitHubCommentKind::PullRequestReview
     }
-}
 
-impl<Command> TryFrom<&GitHubIssueCommentEvent> for Action<Command>
-where
-    Command: TryFrom<RawCommand, Error = anyhow::Error>,
-{
-    type Error = anyhow::Error;
-
-    fn try_from(value: &GitHubIssueCommentEvent) -> Result<Self, Self::Error> {
-        value
-            .comment
-            .body
-            .as_ref()
-            .ok_or_else(|| anyhow!("Body is empty."))?
-            .parse()
-    }
-}
-
-impl<Command> TryFrom<GitHubIssueCommentEvent> for AuthorizedAction<Command>
-where
-    Command: TryFrom<RawCommand, Error = anyhow::Error>,
-{
-    type Error = anyhow::Error;
-
-    fn try_from(value: GitHubIssueCommentEvent) -> Result<Self, Self::Error> {
-        let authorized_by = (&value.comment.user).try_into()?;
-        Ok(Self {
-            action: (&value).try_into()?,
-            context: Box::new(value),
-            authorized_by,
-        })
+    fn get_repo(&self) -> &GitHubRepo {
+        &self.repository
     }
 }
 
 #[async_trait]
 impl AuthorizedActionContext for GitHubIssueCommentEvent {
-    async fn get_pull_request(&self) -> Result<Option<GitHubPullRequest>> {
+    async fn get_pull_request(&self, client: &GitHubClient) -> Result<Option<GitHubPullRequest>> {
         if let Some(pr) = self.issue.pull_request.as_ref() {
-            Ok(client::get(&pr.url).await?.json().await?)
+            Ok(client.get(&self.repository, &pr.url).await?.json().await?)
         } else {
             Ok(None)
         }
@@ -272,7 +432,207 @@ impl AuthorizedActionContext for GitHubIssueCommentEvent {
         &self.comment
     }
 
+    fn get_trigger_kind(&self) -> GitHubCommentKind {
+        GitHubCommentKind::IssueComment
+    }
+
     fn get_repo(&self) -> &GitHubRepo {
         &self.repository
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::{Action, RawCommand, Syntax};
+    use crate::{config::CommandPermission, github::action::spec::CommandSpec};
+
+    static FIXUP: CommandSpec = CommandSpec {
+        name: "fixup",
+        aliases: &["fix"],
+        args: &[],
+        flags: &[],
+        description: "Fixes formatting.",
+        permission: CommandPermission::Maintainer,
+    };
+
+    static MERGE_BASE: CommandSpec = CommandSpec {
+        name: "merge-base",
+        aliases: &[],
+        args: &[],
+        flags: &[],
+        description: "Merges the base branch.",
+        permission: CommandPermission::Maintainer,
+    };
+
+    static APPLY: CommandSpec = CommandSpec {
+        name: "apply",
+        aliases: &[],
+        args: &[],
+        flags: &[],
+        description: "Applies a patch.",
+        permission: CommandPermission::Maintainer,
+    };
+
+    #[derive(Debug, PartialEq)]
+    struct TestCommand(String, Option<String>);
+
+    impl TryFrom<RawCommand> for TestCommand {
+        type Error = anyhow::Error;
+
+        fn try_from(value: RawCommand) -> Result<Self, Self::Error> {
+            match value.raw_command.as_str() {
+                "apply" if value.block.is_none() => anyhow::bail!("/apply is missing its patch."),
+                _ => Ok(Self(value.raw_command, value.block)),
+            }
+        }
+    }
+
+    fn syntax(bare_commands: bool) -> Syntax {
+        Syntax {
+            mentions: vec!["williambotman".to_owned(), "botman".to_owned()],
+            commands: vec![&FIXUP, &MERGE_BASE, &APPLY],
+            bare_commands,
+        }
+    }
+
+    fn parse(body: &str, syntax: &Syntax) -> Option<Vec<(String, TestCommand)>> {
+        Action::<TestCommand>::parse_all(body, syntax)
+            .ok()
+            .filter(|actions| !actions.is_empty())
+            .map(|actions| {
+                actions
+                    .into_iter()
+                    .map(|action| (action.actionee.0, action.command))
+                    .collect()
+            })
+    }
+
+    fn command(actionee: &str, name: &str, arguments: Option<&str>) -> (String, TestCommand) {
+        (
+            actionee.to_owned(),
+            TestCommand(name.to_owned(), arguments.map(ToOwned::to_owned)),
+        )
+    }
+
+    #[test]
+    fn it_should_accept_the_bot_and_its_aliases() {
+        let syntax = syntax(false);
+        assert_eq!(
+            parse("@williambotman /fixup", &syntax),
+            Some(vec![command("williambotman", "fixup", None)])
+        );
+        assert_eq!(
+            parse("@BotMan, /fixup", &syntax),
+            Some(vec![command("botman", "fixup", None)])
+        );
+        assert_eq!(parse("@someone /fixup", &syntax), None);
+        assert_eq!(parse("@williambotman fixup", &syntax), None);
+    }
+
+    #[test]
+    fn it_should_only_accept_bare_commands_when_enabled() {
+        assert_eq!(parse("/fixup", &syntax(false)), None);
+        assert_eq!(
+            parse("  /fixup", &syntax(true)),
+            Some(vec![command("williambotman", "fixup", None)])
+        );
+        assert_eq!(parse("please /fixup", &syntax(true)), None);
+        assert_eq!(
+            parse("/fix", &syntax(true)),
+            Some(vec![command("williambotman", "fix", None)])
+        );
+    }
+
+    #[test]
+    fn it_should_skip_bare_lines_that_are_not_commands() {
+        let syntax = syntax(true);
+        assert!(Action::<TestCommand>::parse_all(
+            "/shrug
+/cc @someone",
+            &syntax
+        )
+        .unwrap()
+        .is_empty());
+        assert_eq!(
+            parse(
+                "/unknown
+/fixup",
+                &syntax
+            ),
+            Some(vec![command("williambotman", "fixup", None)])
+        );
+        assert!(
+            Action::<TestCommand>::parse_all("@williambotman /unknown", &syntax)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(Action::<TestCommand>::parse_all("@williambotman /apply", &syntax).is_err());
+    }
+
+    #[test]
+    fn it_should_take_unknown_commands_after_a_mention_as_prose() {
+        let syntax = syntax(false);
+        assert_eq!(
+            parse("@williambotman /fixup then check /tmp", &syntax),
+            Some(vec![command("williambotman", "fixup", None)])
+        );
+    }
+
+    #[test]
+    fn it_should_find_several_commands_in_order() {
+        let syntax = syntax(false);
+        assert_eq!(
+            parse("thanks! @williambotman /merge-base then /fixup", &syntax),
+            Some(vec![
+                command("williambotman", "merge-base", None),
+                command("williambotman", "fixup", None),
+            ])
+        );
+        assert_eq!(
+            parse(
+                "Looks good.\n\n@williambotman /merge-base\n@botman /fixup.",
+                &syntax
+            ),
+            Some(vec![
+                command("williambotman", "merge-base", None),
+                command("botman", "fixup", None),
+            ])
+        );
+        assert_eq!(
+            parse("@williambotman /fixup /unknown /fix", &syntax),
+            Some(vec![
+                command("williambotman", "fixup", None),
+                command("williambotman", "fix", None),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_should_skip_quotes_and_code_but_not_the_diff_of_apply() {
+        let body = indoc! {"
+            > @williambotman /fixup
+
+            ```
+            @williambotman /merge-base
+            ```
+
+            @williambotman /apply
+
+            ```diff
+            -  a
+            +  b
+            ```
+            @williambotman /fixup
+        "};
+        assert_eq!(
+            parse(body, &syntax(false)),
+            Some(vec![
+                command("williambotman", "apply", Some("```diff\n-  a\n+  b\n```")),
+                command("williambotman", "fixup", None),
+            ])
+        );
+        assert_eq!(parse("> @williambotman /fixup", &syntax(false)), None);
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::{CommandPermission, RepoConfig};
+
+use super::parser::RawCommand;
+
+/// Declares a command: how it's invoked, what it takes and who may run it.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Positional arguments, in order.
+    pub args: &'static [ArgSpec],
+    pub flags: &'static [FlagSpec],
+    pub description: &'static str,
+    /// Who may run the command, unless the repo overrides it.
+    pub permission: CommandPermission,
+}
+
+#[derive(Debug)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+    /// Whether the argument is the fenced block following the command's line, e.g. a diff,
+    /// rather than a word.
+    pub block: bool,
+}
+
+#[derive(Debug)]
+pub struct FlagSpec {
+    /// The name of the flag, without the leading `--`.
+    pub name: &'static str,
+    pub description: &'static str,
+    /// What the flag's value is called, if it takes one (`--flag value` or `--flag=value`).
+    pub value: Option<&'static str>,
+}
+
+/// The arguments of a command, validated against its spec.
+#[derive(Debug, Default)]
+pub struct ParsedArgs {
+    args: HashMap<&'static str, String>,
+    flags: HashMap<&'static str, Option<String>>,
+}
+
+impl ParsedArgs {
+    pub fn arg(&self, name: &str) -> Option<&str> {
+        self.args.get(name).map(String::as_str)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    // No command takes a flag with a value yet.
+    #[cfg(test)]
+    pub fn flag_value(&self, name: &str) -> Option<&str> {
+        self.flags.get(name)?.as_deref()
+    }
+}
+
+impl CommandSpec {
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    /// How the command is written, e.g. `/apply <patch>`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in self.args {
+            if arg.required {
+                usage.push_str(&format!(" <{}>", arg.name));
+            } else {
+                usage.push_str(&format!(" [<{}>]", arg.name));
+            }
+        }
+        for flag in self.flags {
+            match flag.value {
+                Some(value) => usage.push_str(&format!(" [--{} <{}>]", flag.name, value)),
+                None => usage.push_str(&format!(" [--{}]", flag.name)),
+            }
+        }
+        usage
+    }
+
+    /// Validates the words and block following the command against the spec. Words that aren't
+    /// taken as arguments are prose, as in "/merge-base then /fixup", and are ignored.
+    pub fn parse(&self, raw: RawCommand) -> Result<ParsedArgs> {
+        let invalid = |reason: String| anyhow!("{} Usage: `{}`", reason, self.usage());
+        let mut parsed = ParsedArgs::default();
+        let mut positional = self.args.iter().filter(|arg| !arg.block);
+        let mut words = raw.words.into_iter();
+        while let Some(word) = words.next() {
+            if let Some(flag) = word.strip_prefix("--") {
+                let (name, value) = match flag.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_owned())),
+                    None => (flag, None),
+                };
+                let spec = self
+                    .flags
+                    .iter()
+                    .find(|spec| spec.name == name)
+                    .ok_or_else(|| {
+                        invalid(format!("--{} is not a flag of /{}.", name, self.name))
+                    })?;
+                let value = match (spec.value, value) {
+                    (Some(_), Some(value)) => Some(value),
+                    (Some(value_name), None) => Some(words.next().ok_or_else(|| {
+                        invalid(format!("--{} is missing its <{}>.", name, value_name))
+                    })?),
+                    (None, Some(_)) => {
+                        return Err(invalid(format!("--{} doesn't take a value.", name)))
+                    }
+                    (None, None) => None,
+                };
+                parsed.flags.insert(spec.name, value);
+            } else if let Some(arg) = positional.next() {
+                parsed.args.insert(arg.name, word);
+            }
+        }
+        if let (Some(arg), Some(block)) = (self.args.iter().find(|arg| arg.block), raw.block) {
+            parsed.args.insert(arg.name, block);
+        }
+        if let Some(arg) = self
+            .args
+            .iter()
+            .find(|arg| arg.required && !parsed.args.contains_key(arg.name))
+        {
+            bail!(invalid(format!(
+                "/{} is missing its <{}>.",
+                self.name, arg.name
+            )));
+        }
+        Ok(parsed)
+    }
+}
+
+/// Finds the spec `raw` invokes among `specs` and validates its arguments.
+pub fn parse(
+    specs: &[&'static CommandSpec],
+    raw: RawCommand,
+) -> Result<(&'static CommandSpec, ParsedArgs)> {
+    let spec = find(specs, &raw.raw_command).ok_or_else(|| {
+        anyhow!(
+            "/{} is not a command in this repo, see `/help`.",
+            raw.raw_command
+        )
+    })?;
+    Ok((spec, spec.parse(raw)?))
+}
+
+pub fn find(specs: &[&'static CommandSpec], name: &str) -> Option<&'static CommandSpec> {
+    specs.iter().copied().find(|spec| spec.matches(name))
+}
+
+/// Who may run a command in a repo, for `/help`.
+fn permission(spec: &CommandSpec, repo_config: Option<&RepoConfig>) -> &'static str {
+    let permission = repo_config
+        .and_then(|repo_config| repo_config.command_permissions.get(spec.name))
+        .copied()
+        .unwrap_or(spec.permission);
+    match permission {
+        CommandPermission::Maintainer => "Maintainers",
+        CommandPermission::PullRequestAuthor => "Maintainers and the pull request's author",
+        CommandPermission::Anyone => "Anyone",
+    }
+}
+
+/// Renders the reply to `/help`: the commands available in a repo, or the details of `command`.
+pub fn help(
+    specs: &[&'static CommandSpec],
+    mention: &str,
+    repo_config: Option<&RepoConfig>,
+    command: Option<&str>,
+) -> String {
+    if let Some(name) = command {
+        let Some(spec) = find(specs, name) else {
+            return format!("/{} is not a command in this repo, see `/help`.", name);
+        };
+        let mut help = format!("`{}`\n\n{}\n", spec.usage(), spec.description);
+        if !spec.aliases.is_empty() {
+            let aliases = spec
+                .aliases
+                .iter()
+                .map(|alias| format!("`/{}`", alias))
+                .collect::<Vec<_>>();
+            help.push_str(&format!("\nAlso invoked as {}.\n", aliases.join(", ")));
+        }
+        if !spec.args.is_empty() || !spec.flags.is_empty() {
+            help.push('\n');
+        }
+        for arg in spec.args {
+            help.push_str(&format!("- `<{}>`: {}\n", arg.name, arg.description));
+        }
+        for flag in spec.flags {
+            match flag.value {
+                Some(value) => help.push_str(&format!(
+                    "- `--{} <{}>`: {}\n",
+                    flag.name, value, flag.description
+                )),
+                None => help.push_str(&format!("- `--{}`: {}\n", flag.name, flag.description)),
+            }
+        }
+        help.push_str(&format!(
+            "\nMay be run by: {}.",
+            permission(spec, repo_config).to_lowercase()
+        ));
+        return help;
+    }
+
+    let mut help = format!(
+        "Commands are run by mentioning @{} in a comment, e.g. `@{} /help fixup`",
+        mention, mention
+    );
+    if repo_config.is_some_and(|repo_config| repo_config.bare_commands) {
+        help.push_str(", or by starting a line with the command");
+    }
+    help.push_str(
+        ". Several commands may be given at once, they're run in order.\n\n\
+         | Command | Description | May be run by |\n\
+         | --- | --- | --- |\n",
+    );
+    for spec in specs {
+        help.push_str(&format!(
+            "| `{}` | {} | {} |\n",
+            spec.usage(),
+            spec.description,
+            permission(spec, repo_config)
+        ));
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::config::{CommandPermission, RepoConfig};
+
+    use super::{help, parse, ArgSpec, CommandSpec, FlagSpec, RawCommand};
+
+    static APPLY: CommandSpec = CommandSpec {
+        name: "apply",
+        aliases: &["patch"],
+        args: &[ArgSpec {
+            name: "patch",
+            description: "The patch, as a diff block.",
+            required: true,
+            block: true,
+        }],
+        flags: &[FlagSpec {
+            name: "message",
+            description: "The commit message.",
+            value: Some("message"),
+        }],
+        description: "Applies a patch.",
+        permission: CommandPermission::Maintainer,
+    };
+
+    static FIXUP: CommandSpec = CommandSpec {
+        name: "fixup",
+        aliases: &[],
+        args: &[],
+        flags: &[FlagSpec {
+            name: "no-merge",
+            description: "Don't merge the base branch first.",
+            value: None,
+        }],
+        description: "Fixes formatting.",
+        permission: CommandPermission::PullRequestAuthor,
+    };
+
+    fn raw(name: &str, words: &[&str], block: Option<&str>) -> RawCommand {
+        RawCommand {
+            raw_command: name.to_owned(),
+            words: words.iter().map(|word| word.to_string()).collect(),
+            block: block.map(ToOwned::to_owned),
+        }
+    }
+
+    #[test]
+    fn it_should_parse_arguments_and_flags() {
+        let (spec, args) = parse(
+            &[&APPLY, &FIXUP],
+            raw("patch", &["--message=fix", "typo"], Some("```diff\n```")),
+        )
+        .unwrap();
+        assert_eq!(spec.name, "apply");
+        assert_eq!(args.arg("patch"), Some("```diff\n```"));
+        assert_eq!(args.flag_value("message"), Some("fix"));
+
+        let (_, args) = parse(
+            &[&APPLY, &FIXUP],
+            raw("fixup", &["--no-merge", "then"], None),
+        )
+        .unwrap();
+        assert!(args.flag("no-merge"));
+    }
+
+    #[test]
+    fn it_should_explain_invalid_commands() {
+        let specs = [&APPLY, &FIXUP];
+        let error = |raw| parse(&specs, raw).err().unwrap().to_string();
+        assert_eq!(
+            error(raw("apply", &[], None)),
+            "/apply is missing its <patch>. Usage: `/apply <patch> [--message <message>]`"
+        );
+        assert_eq!(
+            error(raw("apply", &["--message"], Some("```diff\n```"))),
+            "--message is missing its <message>. Usage: `/apply <patch> [--message <message>]`"
+        );
+        assert_eq!(
+            error(raw("fixup", &["--force"], None)),
+            "--force is not a flag of /fixup. Usage: `/fixup [--no-merge]`"
+        );
+        assert_eq!(
+            error(raw("deploy", &[], None)),
+            "/deploy is not a command in this repo, see `/help`."
+        );
+    }
+
+    #[test]
+    fn it_should_list_commands_with_their_permissions() {
+        let repo_config = RepoConfig {
+            command_permissions: BTreeMap::from([(
+                "fixup".to_owned(),
+                CommandPermission::Maintainer,
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(
+            help(&[&APPLY, &FIXUP], "williambotman", Some(&repo_config), None),
+            "Commands are run by mentioning @williambotman in a comment, e.g. `@williambotman /help fixup`. Several commands may be given at once, they're run in order.\n\n\
+             | Command | Description | May be run by |\n\
+             | --- | --- | --- |\n\
+             | `/apply <patch> [--message <message>]` | Applies a patch. | Maintainers |\n\
+             | `/fixup [--no-merge]` | Fixes formatting. | Maintainers |\n"
+        );
+        assert_eq!(
+            help(&[&APPLY, &FIXUP], "williambotman", None, Some("patch")),
+            "`/apply <patch> [--message <message>]`\n\nApplies a patch.\n\n\
+             Also invoked as `/patch`.\n\n\
+             - `<patch>`: The patch, as a diff block.\n\
+             - `--message <message>`: The commit message.\n\n\
+             May be run by: maintainers."
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION},
+    Client, Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{
+    config::GitHubConfig,
+    github::{client, data::GitHubRepoId},
+};
+
+/// Installation tokens are refreshed when they are this close to expiring, so that a token handed
+/// out is still valid for the duration of a workspace run.
+const TOKEN_REFRESH_MARGIN_MINUTES: i64 = 10;
+
+/// What requests and git operations are authenticated with.
+pub enum Credentials {
+    /// A personal access token.
+    Token(String),
+    /// A GitHub App, authenticating with per-installation access tokens.
+    App(Box<GitHubApp>),
+}
+
+impl Credentials {
+    pub fn from_config(config: &GitHubConfig, http: &Client) -> Self {
+        match (&config.token, &config.app) {
+            (_, Some(app)) => Credentials::App(Box::new(GitHubApp::new(
+                http.clone(),
+                app.id,
+                EncodingKey::from_rsa_pem(app.private_key.expose().as_bytes())
+                    .expect("Invalid GitHub App private key."),
+                &config.api_url,
+            ))),
+            (Some(token), None) => Credentials::Token(token.expose().to_owned()),
+            (None, None) => panic!("No GitHub credentials configured."),
+        }
+    }
+
+    /// Returns the token to authenticate requests and git operations against `repo` with.
+    pub async fn token(&self, repo: &GitHubRepoId) -> Result<String> {
+        match self {
+            Credentials::Token(token) => Ok(token.to_owned()),
+            Credentials::App(app) => app.token_for_repo(repo).await,
+        }
+    }
+
+    /// Checks that the credentials authenticate against the REST API at `api_url`.
+    pub async fn verify(&self, http: &Client, api_url: &str) -> Result<()> {
+        match self {
+            Credentials::Token(token) => {
+                let url = format!("{}/user", api_url);
+                authenticated_get(http, &url, format!("token {}", token))
+                    .await
+                    .map(|_| ())
+            }
+            Credentials::App(app) => app.verify().await,
+        }
+    }
+
+    /// The name the bot is mentioned with: the login of the token's user, or the App's slug.
+    pub async fn identity(&self, http: &Client, api_url: &str) -> Result<String> {
+        match self {
+            Credentials::Token(token) => {
+                let url = format!("{}/user", api_url);
+                let user: Identity = authenticated_get(http, &url, format!("token {}", token))
+                    .await?
+                    .json()
+                    .await?;
+                user.login
+                    .ok_or_else(|| anyhow!("The authenticated user has no login."))
+            }
+            Credentials::App(app) => app.identity().await,
+        }
+    }
+
+    /// Remembers which installation a repo belongs to, as reported by an incoming webhook.
+    pub async fn register_installation(&self, repo: &GitHubRepoId, installation_id: u64) {
+        if let Credentials::App(app) = self {
+            app.register_installation(repo, installation_id).await;
+        }
+    }
+}
+
+async fn authenticated_get(http: &Client, url: &str, authorization: String) -> Result<Response> {
+    let response = http
+        .get(url)
+        .header(ACCEPT, "application/vnd.github+json")
+        .header(AUTHORIZATION, authorization)
+        .send()
+        .await;
+    client::record_response(url, &response);
+    let response = response?;
+    if !response.status().is_success() {
+        bail!(
+            "GitHub rejected the credentials, response status: {}",
+            response.status()
+        );
+    }
+    Ok(response)
+}
+
+#[derive(Serialize)]
+struct AppClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+/// The authenticated user (`login`) or App (`slug`).
+#[derive(Deserialize)]
+struct Identity {
+    login: Option<String>,
+    slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+#[derive(Deserialize, Clone)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+pub struct GitHubApp {
+    http: Client,
+    id: u64,
+    key: EncodingKey,
+    api_url: String,
+    installations: Mutex<HashMap<String, u64>>,
+    tokens: Mutex<HashMap<u64, InstallationToken>>,
+}
+
+impl GitHubApp {
+    pub fn new(http: Client, id: u64, key: EncodingKey, api_url: &str) -> Self {
+        Self {
+            http,
+            id,
+            key,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            installations: Mutex::new(HashMap::new()),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signs a short-lived JWT that authenticates as the app itself.
+    fn jwt(&self) -> Result<String> {
+        let now = Utc::now();
+        let claims = AppClaims {
+            // Backdated to allow for clock drift, as recommended by GitHub.
+            iat: (now - Duration::seconds(60)).timestamp(),
+            exp: (now + Duration::minutes(9)).timestamp(),
+            iss: self.id.to_string(),
+        };
+        Ok(jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &self.key,
+        )?)
+    }
+
+    async fn verify(&self) -> Result<()> {
+        let url = format!("{}/app", self.api_url);
+        authenticated_get(&self.http, &url, format!("Bearer {}", self.jwt()?))
+            .await
+            .map(|_| ())
+    }
+
+    async fn identity(&self) -> Result<String> {
+        let url = format!("{}/app", self.api_url);
+        let app: Identity = authenticated_get(&self.http, &url, format!("Bearer {}", self.jwt()?))
+            .await?
+            .json()
+            .await?;
+        app.slug
+            .ok_or_else(|| anyhow!("The authenticated App has no slug."))
+    }
+
+    async fn register_installation(&self, repo: &GitHubRepoId, installation_id: u64) {
+        self.installations
+            .lock()
+            .await
+            .insert(repo.to_string(), installation_id);
+    }
+
+    async fn installation_id(&self, repo: &GitHubRepoId) -> Result<u64> {
+        if let Some(id) = self.installations.lock().await.get(&repo.to_string()) {
+            return Ok(*id);
+        }
+        let url = format!("{}/repos/{}/installation", self.api_url, repo);
+        let response = self
+            .http
+            .get(&url)
+            .header(ACCEPT, "application/vnd.github+json")
+            .header(AUTHORIZATION, format!("Bearer {}", self.jwt()?))
+            .send()
+            .await;
+        client::record_response(&url, &response);
+        let response = response?;
+        if !response.status().is_success() {
+            bail!(
+                "Failed to look up app installation for {}, response status: {}",
+                repo,
+                response.status()
+            );
+        }
+        let installation: Installation = response.json().await?;
+        self.register_installation(repo, installation.id).await;
+        Ok(installation.id)
+    }
+
+    async fn token_for_repo(&self, repo: &GitHubRepoId) -> Result<String> {
+        let installation_id = self.installation_id(repo).await?;
+        self.installation_token(installation_id).await
+    }
+
+    /// Returns a cached installation access token, exchanging a new one if it's missing or about
+    /// to expire.
+    pub async fn installation_token(&self, installation_id: u64) -> Result<String> {
+        let mut tokens = self.tokens.lock().await;
+        if let Some(token) = tokens.get(&installation_id) {
+            if token.expires_at - Utc::now() > Duration::minutes(TOKEN_REFRESH_MARGIN_MINUTES) {
+                return Ok(token.token.to_owned());
+            }
+        }
+
+        info!(
+            installation = installation_id,
+            "Exchanging installation access token."
+        );
+        let url = format!(
+            "{}/app/installations/{}/access_tokens",
+            self.api_url, installation_id
+        );
+        let response = self
+            .http
+            .post(&url)
+            .header(ACCEPT, "application/vnd.github+json")
+            .header(AUTHORIZATION, format!("Bearer {}", self.jwt()?))
+            .send()
+            .await;
+        client::record_response(&url, &response);
+        let response = response?;
+        if !response.status().is_success() {
+            bail!(
+                "Failed to create access token for installation {}, response status: {}",
+                installation_id,
+                response.status()
+            );
+        }
+        let token: InstallationToken = response
+            .json()
+            .await
+            .map_err(|err| anyhow!("Malformed installation token response: {}", err))?;
+        tokens.insert(installation_id, token.clone());
+        Ok(token.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Credentials, GitHubApp};
+    use crate::github::client::http_client;
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header_regex, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const PRIVATE_KEY: &str = include_str!("testdata/app-private-key.pem");
+    const PUBLIC_KEY: &str = include_str!("testdata/app-public-key.pem");
+
+    fn app(server: &MockServer) -> GitHubApp {
+        GitHubApp::new(
+            http_client(),
+            1337,
+            EncodingKey::from_rsa_pem(PRIVATE_KEY.as_bytes()).unwrap(),
+            &server.uri(),
+        )
+    }
+
+    fn token_response(token: &str, expires_in: Duration) -> ResponseTemplate {
+        ResponseTemplate::new(201).set_body_json(json!({
+            "token": token,
+            "expires_at": (Utc::now() + expires_in).to_rfc3339(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn it_should_sign_jwt_with_app_id() {
+        let server = MockServer::start().await;
+        let jwt = app(&server).jwt().unwrap();
+        let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
+        validation.set_required_spec_claims(&["exp", "iat", "iss"]);
+        let claims = jsonwebtoken::decode::<serde_json::Value>(
+            &jwt,
+            &DecodingKey::from_rsa_pem(PUBLIC_KEY.as_bytes()).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(claims["iss"], "1337");
+    }
+
+    #[tokio::test]
+    async fn it_should_exchange_and_cache_installation_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/42/access_tokens"))
+            .and(header_regex("authorization", "^Bearer "))
+            .respond_with(token_response("ghs_first", Duration::hours(1)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let app = app(&server);
+        assert_eq!(app.installation_token(42).await.unwrap(), "ghs_first");
+        assert_eq!(app.installation_token(42).await.unwrap(), "ghs_first");
+    }
+
+    #[tokio::test]
+    async fn it_should_refresh_token_about_to_expire() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/42/access_tokens"))
+            .respond_with(token_response("ghs_expiring", Duration::minutes(1)))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/42/access_tokens"))
+            .respond_with(token_response("ghs_fresh", Duration::hours(1)))
+            .mount(&server)
+            .await;
+
+        let app = app(&server);
+        assert_eq!(app.installation_token(42).await.unwrap(), "ghs_expiring");
+        assert_eq!(app.installation_token(42).await.unwrap(), "ghs_fresh");
+    }
+
+    #[tokio::test]
+    async fn it_should_resolve_installation_by_repo() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/williamboman/mason.nvim/installation"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 7 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/7/access_tokens"))
+            .respond_with(token_response("ghs_mason", Duration::hours(1)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/8/access_tokens"))
+            .respond_with(token_response("ghs_registry", Duration::hours(1)))
+            .mount(&server)
+            .await;
+
+        let app = app(&server);
+        let mason = "williamboman/mason.nvim".parse().unwrap();
+        let registry = "mason-org/mason-registry".parse().unwrap();
+        app.register_installation(&registry, 8).await;
+        assert_eq!(app.token_for_repo(&mason).await.unwrap(), "ghs_mason");
+        assert_eq!(app.token_for_repo(&mason).await.unwrap(), "ghs_mason");
+        assert_eq!(app.token_for_repo(&registry).await.unwrap(), "ghs_registry");
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_when_exchange_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/app/installations/42/access_tokens"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        assert!(app(&server).installation_token(42).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_verify_credentials() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/app"))
+            .and(header_regex("authorization", "^Bearer "))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1337 })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .and(header_regex("authorization", "^token ghp_valid$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "login": "bot" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        assert!(app(&server).verify().await.is_ok());
+        for (token, valid) in [("ghp_valid", true), ("ghp_revoked", false)] {
+            let credentials = Credentials::Token(token.to_owned());
+            assert_eq!(
+                credentials
+                    .verify(&http_client(), &server.uri())
+                    .await
+                    .is_ok(),
+                valid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_resolve_identity() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/app"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "id": 1337, "slug": "williambotman" })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "login": "botman" })))
+            .mount(&server)
+            .await;
+
+        assert_eq!(app(&server).identity().await.unwrap(), "williambotman");
+        assert_eq!(
+            Credentials::Token("ghp_valid".to_owned())
+                .identity(&http_client(), &server.uri())
+                .await
+                .unwrap(),
+            "botman"
+        );
+    }
+}
@@ -1,131 +1,86 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{CLIENT, GITHUB_PAT};
+use crate::{
+    config::GitHubConfig,
+    jobs, metrics,
+    store::{PlannedOperationRecord, Store},
+};
 
-use super::data::{GitHubComment, GitHubReaction, GitHubRepo};
+use super::{
+    auth::Credentials,
+    data::{
+        GitHubCheckRun, GitHubCheckRunList, GitHubCollaboratorPermission, GitHubComment,
+        GitHubCommentKind, GitHubIssueLabel, GitHubPullRequestFile, GitHubReaction, GitHubRepo,
+        GitHubRepoId, GitHubRepoPermission, GitHubTeamMembership, GitHubUser,
+    },
+    paginate::{self, Connection},
+    retry::{Failure, Idempotency, RetryPolicy},
+};
 use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use futures::{stream, Stream, TryStreamExt};
 use reqwest::{
     header::{HeaderMap, ACCEPT, AUTHORIZATION, USER_AGENT},
-    Response,
+    Client, RequestBuilder, Response, StatusCode,
 };
 use rocket::serde::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
+use tracing::{error, info, warn};
 
-// TODO maybe create a struct or something idk
-
-lazy_static! {
-    static ref HEADERS: HeaderMap = {
-        let mut header_map = HeaderMap::new();
-        header_map.insert(ACCEPT, "application/json".parse().unwrap());
-        header_map.insert(
-            AUTHORIZATION,
-            format!("token {}", GITHUB_PAT.as_str()).parse().unwrap(),
-        );
-        header_map.insert(
-            USER_AGENT,
-            "botman (+https://github.com/williamboman/botman)"
-                .parse()
-                .unwrap(),
-        );
-        header_map
-    };
+/// Builds the HTTP client all requests to GitHub are made with.
+pub fn http_client() -> Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, "application/json".parse().unwrap());
+    headers.insert(
+        USER_AGENT,
+        "botman (+https://github.com/williamboman/botman)"
+            .parse()
+            .unwrap(),
+    );
+    Client::builder()
+        .default_headers(headers)
+        .build()
+        .expect("Failed to build HTTP client.")
 }
 
 const COMMENT_FOOTER: &str = r#"
 
 <sup>` 🤖 This is an automated comment. `  [` 📖 Source code `](https://github.com/williamboman/botman)</sup>"#;
 
-pub async fn create_issue_comment(
-    repo: &GitHubRepo,
-    issue_number: u64,
-    comment: &str,
-) -> Result<GitHubComment> {
-    let merged_comment = comment.to_string() + COMMENT_FOOTER;
-    println!(
-        "Creating issue comment {:?} {} {:?}",
-        merged_comment, issue_number, repo
-    );
-    post_json(
-        format!("{}/issues/{}/comments", repo.as_api_url(), issue_number).as_str(),
-        &HashMap::from([("body", &merged_comment)]),
-    )
-    .await
-    .inspect_err(|e| {
-        eprintln!("{}", e);
-        eprintln!(
-            "Failed to create issue comment {:?} {} {:?}",
-            merged_comment, issue_number, repo
-        )
-    })
+const ADD_REACTION_MUTATION: &str = r#"
+mutation addReaction($input: AddReactionInput!) {
+    addReaction(input: $input) {
+        reaction {
+            content
+        }
+    }
 }
+"#;
 
-pub async fn create_issue_comment_reaction(
-    repo: &GitHubRepo,
-    comment: &GitHubComment,
-    reaction: &GitHubReaction,
-) -> Result<Value> {
-    println!(
-        "Creating issue comment reaction {:?} {:?} {:?}",
-        reaction, comment, repo
-    );
-    post_json(
-        format!(
-            "{}/issues/comments/{}/reactions",
-            repo.as_api_url(),
-            comment.id
-        )
-        .as_str(),
-        &HashMap::from([("content", reaction)]),
-    )
-    .await
-    .inspect_err(|e| {
-        eprintln!("{}", e);
-        eprintln!(
-            "Failed to create issue comment reaction {:?} {:?} {:?}",
-            reaction, comment, repo
-        )
-    })
-}
-
-pub async fn add_labels_to_issue(
-    repo: &GitHubRepo,
-    labels: Vec<&str>,
-    issue_number: u64,
-) -> Result<Value> {
-    println!("Adding labels to issue {:?} {}", labels, issue_number);
-    post_json(
-        format!("{}/issues/{}/labels", repo.as_api_url(), issue_number).as_str(),
-        &HashMap::from([("labels", &labels)]),
-    )
-    .await
-    .inspect_err(|e| {
-        eprintln!("{}", e);
-        eprintln!(
-            "Failed to add labels to issue #{} {:?}",
-            issue_number, labels
-        );
-    })
+#[derive(Deserialize)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct AddedReaction {
+    pub content: String,
 }
 
-pub async fn create_column_card(column_id: u64, issue_id: u64) -> Result<Value> {
-    println!("Creating column card {} {}", column_id, issue_id);
-    post_json(
-        format!(
-            "https://api.github.com/projects/columns/{}/cards",
-            column_id
-        )
-        .as_str(),
-        &HashMap::from([
-            ("content_type", Value::String("Issue".to_string())),
-            ("content_id", Value::Number(issue_id.into())),
-        ]),
-    )
-    .await
-    .inspect_err(|e| {
-        eprintln!("{}", e);
-        eprintln!("Failed to create column card {} {}", column_id, issue_id);
-    })
+#[derive(Deserialize)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct AddReaction {
+    pub reaction: AddedReaction,
+}
+
+#[derive(Deserialize)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct AddReactionResponse {
+    pub addReaction: AddReaction,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+struct AddReactionInput {
+    content: &'static str,
+    subjectId: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -134,34 +89,6 @@ pub struct RequestReviewersDto {
     pub team_reviewers: Vec<String>,
 }
 
-pub async fn request_review(
-    repo: &GitHubRepo,
-    pull_request_number: u64,
-    reviewers: &RequestReviewersDto,
-) -> Result<Value> {
-    println!(
-        "Requesting reviewers {:?} {} {:?}",
-        repo, pull_request_number, reviewers
-    );
-    post_json(
-        format!(
-            "{}/pulls/{}/requested_reviewers",
-            repo.as_api_url(),
-            pull_request_number
-        )
-        .as_str(),
-        reviewers,
-    )
-    .await
-    .inspect_err(|e| {
-        eprintln!("{}", e);
-        eprintln!(
-            "Failed to request reviewers {:?} {} {:?}",
-            repo, pull_request_number, reviewers
-        );
-    })
-}
-
 const MINIMIZE_COMMENT_MUTATION: &str = r#"
 mutation minimizeComment($input: MinimizeCommentInput!) {
     minimizeComment(input: $input) {
@@ -212,7 +139,7 @@ pub struct UnminimizeCommentResponse {
     pub unminimizeComment: UnminimizeComment,
 }
 
-#[allow(non_camel_case_types, dead_code)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms, dead_code)]
 #[derive(Serialize)]
 enum ReportedContentClassifier {
     ABUSE,
@@ -238,102 +165,10 @@ struct UnminimizeCommentInput {
     subjectId: String,
 }
 
-pub async fn minimize_comment(comment: &GitHubComment) -> Result<MinimizeCommentResponse> {
-    println!("Minimizing comment {:?}", comment);
-    let mut variables = Map::new();
-    variables.insert(
-        "input".to_owned(),
-        json!(MinimizeCommentInput {
-            classifier: ReportedContentClassifier::RESOLVED,
-            clientMutationId: None, // dafuq is this?
-            subjectId: comment.node_id.to_owned(),
-        }),
-    );
-
-    let data = graphql::<MinimizeCommentResponse>(&GraphqlQuery {
-        query: MINIMIZE_COMMENT_MUTATION.to_owned(),
-        variables: Some(variables),
-    })
-    .await?;
-
-    if data.minimizeComment.minimizedComment.isMinimized {
-        Ok(data)
-    } else {
-        bail!("Failed to minimize comment.")
-    }
-}
-
-pub async fn unminimize_comment(comment: &GitHubComment) -> Result<UnminimizeCommentResponse> {
-    println!("Unminimizing comment {:?}", comment);
-    let mut variables = Map::new();
-    variables.insert(
-        "input".to_owned(),
-        json!(UnminimizeCommentInput {
-            clientMutationId: None, // dafuq is this?
-            subjectId: comment.node_id.to_owned(),
-        }),
-    );
-    let data = graphql::<UnminimizeCommentResponse>(&GraphqlQuery {
-        query: UNMINIMIZE_COMMENT_MUTATION.to_owned(),
-        variables: Some(variables),
-    })
-    .await
-    .inspect_err(|e| {
-        eprintln!("{:?}", e);
-        eprintln!("Failed to unminimize comment {:?}", comment)
-    })?;
-
-    if data.unminimizeComment.unminimizedComment.isMinimized {
-        Ok(data)
-    } else {
-        bail!("Failed to minimize comment")
-    }
-}
-
-pub async fn get(url: &str) -> Result<Response> {
-    let response = CLIENT.get(url).headers(HEADERS.clone()).send().await;
+pub(super) fn record_response(url: &str, response: &reqwest::Result<Response>) {
     match response {
-        Ok(response) if response.status().is_success() => Ok(response),
-        Ok(err_response) => {
-            eprintln!("{:?}", err_response);
-            bail!(
-                "Failed to fetch url {}, response status: {}",
-                url,
-                err_response.status()
-            )
-        }
-        Err(err) => {
-            eprintln!("{:?}", err);
-            bail!("Failed to fetch url {}, response status: ", url)
-        }
-    }
-}
-
-pub async fn post_json<Payload: Serialize, Response: DeserializeOwned>(
-    url: &str,
-    payload: &Payload,
-) -> Result<Response> {
-    let response = CLIENT
-        .post(url)
-        .headers(HEADERS.clone())
-        .json(payload)
-        .send()
-        .await;
-
-    match response {
-        Ok(response) if response.status().is_success() => Ok(response.json().await?),
-        Ok(err_response) => {
-            eprintln!("{:?}", err_response);
-            bail!(
-                "Failed to call {}, response status: {}",
-                url,
-                err_response.status()
-            );
-        }
-        Err(err) => {
-            eprintln!("{:?}", err);
-            bail!("Failed to call {}", url);
-        }
+        Ok(response) => metrics::github_response(url, response),
+        Err(_) => metrics::github_request_error(url),
     }
 }
 
@@ -343,17 +178,79 @@ pub struct GraphqlQuery {
     variables: Option<Map<String, Value>>,
 }
 
+impl GraphqlQuery {
+    pub fn new(query: &str, variables: Map<String, Value>) -> Self {
+        Self {
+            query: query.to_owned(),
+            variables: Some(variables),
+        }
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct GraphqlErrorLocation {
     pub line: u32,
     pub column: u32,
 }
 
+/// The kind of a GraphQL error, as reported in its `type`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphqlErrorKind {
+    NotFound,
+    Forbidden,
+    RateLimited,
+    Other(String),
+}
+
+impl From<&str> for GraphqlErrorKind {
+    fn from(kind: &str) -> Self {
+        match kind {
+            "NOT_FOUND" => Self::NotFound,
+            "FORBIDDEN" => Self::Forbidden,
+            "RATE_LIMITED" => Self::RateLimited,
+            other => Self::Other(other.to_owned()),
+        }
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct GraphqlResponseError {
     pub message: String,
+    #[serde(default)]
     pub locations: Vec<GraphqlErrorLocation>,
-    pub path: Vec<String>,
+    /// The path of the field that failed, made up of field names and list indices.
+    #[serde(default)]
+    pub path: Vec<Value>,
+    #[serde(rename = "type")]
+    pub r#type: Option<String>,
+}
+
+impl GraphqlResponseError {
+    pub fn kind(&self) -> Option<GraphqlErrorKind> {
+        self.r#type.as_deref().map(GraphqlErrorKind::from)
+    }
+}
+
+impl std::fmt::Display for GraphqlResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(r#type) = &self.r#type {
+            write!(f, "{} ", r#type)?;
+        }
+        if !self.path.is_empty() {
+            let path = self
+                .path
+                .iter()
+                .map(|segment| match segment {
+                    Value::String(field) => field.to_owned(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>();
+            write!(f, "at {}: ", path.join("."))?;
+        }
+        f.write_str(&self.message)
+    }
 }
 
 #[derive(Deserialize)]
@@ -362,11 +259,68 @@ pub struct GraphqlResponseEnvelope<Data> {
     pub errors: Option<Vec<GraphqlResponseError>>,
 }
 
+/// Why a GraphQL request failed. Returned from [`GitHubClient::graphql`] and kept as the source of
+/// errors built on top of it, so that callers can react to e.g. [`GraphqlErrorKind::NotFound`]
+/// through [`GraphqlError::find`].
 #[derive(Debug)]
 pub enum GraphqlError {
+    Credentials(anyhow::Error),
     Request(reqwest::Error),
+    Status(reqwest::StatusCode),
     Response(Vec<GraphqlResponseError>),
     NoData,
+    Decode(serde_json::Error),
+}
+
+impl GraphqlError {
+    /// Finds a GraphQL error in the chain of `err`.
+    pub fn find(err: &anyhow::Error) -> Option<&GraphqlError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<GraphqlError>())
+    }
+
+    /// Whether any of the errors GitHub responded with is of `kind`. Rate limits are also
+    /// recognized by the response status.
+    pub fn is(&self, kind: GraphqlErrorKind) -> bool {
+        match self {
+            Self::Response(errors) => errors
+                .iter()
+                .any(|error| error.kind() == Some(kind.clone())),
+            Self::Status(status) => {
+                kind == GraphqlErrorKind::RateLimited
+                    && *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for GraphqlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Credentials(err) => write!(f, "Failed to authenticate GraphQL request: {}", err),
+            Self::Request(err) => write!(f, "Failed to call GraphQL: {}", err),
+            Self::Status(status) => {
+                write!(f, "Failed to call GraphQL, response status: {}", status)
+            }
+            Self::Response(errors) => {
+                let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "Received GraphQL errors: {}", errors.join("; "))
+            }
+            Self::NoData => f.write_str("Didn't receive GraphQL data or errors."),
+            Self::Decode(err) => write!(f, "Failed to decode GraphQL data: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GraphqlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(err) => Some(err),
+            Self::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl From<reqwest::Error> for GraphqlError {
@@ -400,27 +354,1441 @@ impl<Data> GraphqlResponseEnvelope<Data> {
     }
 }
 
-pub async fn graphql<Data: DeserializeOwned>(query: &GraphqlQuery) -> Result<Data> {
-    let response = CLIENT
-        .post("https://api.github.com/graphql")
-        .headers(HEADERS.clone())
-        .json(query)
-        .send()
-        .await?;
+/// A client for the REST and GraphQL APIs of a GitHub instance (github.com or a GitHub Enterprise
+/// Server), authenticated with the configured credentials.
+#[derive(Clone)]
+pub struct GitHubClient {
+    http: Client,
+    api_url: String,
+    graphql_url: String,
+    git_url: String,
+    credentials: Arc<Credentials>,
+    retry: RetryPolicy,
+    /// Where mutations are recorded instead of performed, in dry-run mode.
+    dry_run: Option<Arc<Store>>,
+}
 
-    response
-        .json::<GraphqlResponseEnvelope<Data>>()
-        .await?
-        .ok()
-        .map_err(|e| {
-            eprintln!("{:?}", e);
-            match e {
-                GraphqlError::Request(req_err) => anyhow!(
-                    "Failed to call GraphQL, response status: {:?}",
-                    req_err.status()
-                ),
-                GraphqlError::Response(_) => anyhow!("Received GraphQL errors."),
-                GraphqlError::NoData => anyhow!("Didn't receive GraphQL data or errors."),
+impl std::fmt::Debug for GitHubClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHubClient")
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+
+impl GitHubClient {
+    pub fn new(
+        http: Client,
+        api_url: &str,
+        graphql_url: &str,
+        git_url: &str,
+        credentials: Credentials,
+    ) -> Self {
+        Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            graphql_url: graphql_url.to_owned(),
+            git_url: git_url.trim_end_matches('/').to_owned(),
+            credentials: Arc::new(credentials),
+            retry: RetryPolicy::default(),
+            dry_run: None,
+        }
+    }
+
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+
+    /// Records mutations to `store` rather than performing them.
+    pub fn with_dry_run(self, store: Arc<Store>) -> Self {
+        Self {
+            dry_run: Some(store),
+            ..self
+        }
+    }
+
+    /// In dry-run mode, records `operation` as planned and returns `true`, in which case the caller
+    /// must skip it.
+    pub fn skip_in_dry_run(&self, repo: &GitHubRepoId, operation: &str, details: Value) -> bool {
+        let Some(store) = &self.dry_run else {
+            return false;
+        };
+        info!(%repo, operation, %details, "Dry run, skipping operation.");
+        let job_id = jobs::current_job_id();
+        let record = PlannedOperationRecord {
+            job_id: job_id.as_deref(),
+            repo: repo.to_string(),
+            operation,
+            details: details.to_string(),
+        };
+        if let Err(err) = store.record_planned_operation(&record) {
+            error!(error = ?err, operation, "Failed to record planned operation.");
+        }
+        true
+    }
+
+    pub fn from_config(config: &GitHubConfig) -> Self {
+        let http = http_client();
+        let credentials = Credentials::from_config(config, &http);
+        Self::new(
+            http,
+            &config.api_url,
+            &config.graphql_url,
+            &config.git_url,
+            credentials,
+        )
+        .with_retry((&config.retry).into())
+    }
+
+    /// The base URL of git remotes, e.g. `https://github.com`.
+    pub fn git_url(&self) -> &str {
+        &self.git_url
+    }
+
+    /// The REST API URL of `repo`.
+    pub fn repo_url(&self, repo: &GitHubRepoId) -> String {
+        format!("{}/repos/{}/{}", self.api_url, repo.owner, repo.name)
+    }
+
+    /// The URL `repo` is cloned from.
+    pub fn repo_git_url(&self, repo: &GitHubRepoId) -> String {
+        format!("{}/{}/{}.git", self.git_url, repo.owner, repo.name)
+    }
+
+    /// Returns the token to authenticate requests and git operations against `repo` with.
+    pub async fn token(&self, repo: &GitHubRepoId) -> Result<String> {
+        self.credentials.token(repo).await
+    }
+
+    /// Remembers which installation a repo belongs to, as reported by an incoming webhook.
+    pub async fn register_installation(&self, repo: &GitHubRepoId, installation_id: u64) {
+        self.credentials
+            .register_installation(repo, installation_id)
+            .await
+    }
+
+    /// Checks that the credentials authenticate against the GitHub API.
+    pub async fn verify(&self) -> Result<()> {
+        self.credentials.verify(&self.http, &self.api_url).await
+    }
+
+    /// The name the bot is mentioned with, as authenticated.
+    pub async fn identity(&self) -> Result<String> {
+        self.credentials.identity(&self.http, &self.api_url).await
+    }
+
+    /// The permission `user` has on `repo`, through their own or their teams' access.
+    pub async fn get_collaborator_permission(
+        &self,
+        repo: &GitHubRepoId,
+        user: &str,
+    ) -> Result<GitHubRepoPermission> {
+        let url = format!("{}/collaborators/{}/permission", self.repo_url(repo), user);
+        match self.get_if_found(repo, &url).await? {
+            Some(response) => Ok(response
+                .json::<GitHubCollaboratorPermission>()
+                .await?
+                .effective()),
+            None => Ok(GitHubRepoPermission::None),
+        }
+    }
+
+    /// Whether `user` is an active member of the `org/team_slug` team. `repo` is only used to
+    /// authenticate the request.
+    pub async fn is_team_member(
+        &self,
+        repo: &GitHubRepoId,
+        org: &str,
+        team_slug: &str,
+        user: &str,
+    ) -> Result<bool> {
+        let url = format!(
+            "{}/orgs/{}/teams/{}/memberships/{}",
+            self.api_url, org, team_slug, user
+        );
+        match self.get_if_found(repo, &url).await? {
+            Some(response) => Ok(response.json::<GitHubTeamMembership>().await?.state == "active"),
+            None => Ok(false),
+        }
+    }
+
+    pub async fn create_issue_comment(
+        &self,
+        repo: &GitHubRepo,
+        issue_number: u64,
+        comment: &str,
+    ) -> Result<GitHubComment> {
+        let merged_comment = comment.to_string() + COMMENT_FOOTER;
+        info!(repo = %repo.full_name, issue = issue_number, "Creating issue comment.");
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "create_issue_comment",
+            json!({ "issue": issue_number, "body": merged_comment }),
+        ) {
+            return Ok(GitHubComment {
+                id: 0,
+                node_id: String::new(),
+                body: Some(merged_comment),
+                user: GitHubUser {
+                    id: 0,
+                    login: String::new(),
+                },
+                updated_at: None,
+            });
+        }
+        self.post_json(
+            repo,
+            format!("{}/issues/{}/comments", self.repo_url(&repo.full_name), issue_number).as_str(),
+            &HashMap::from([("body", &merged_comment)]),
+            Idempotency::NonIdempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = %e, repo = %repo.full_name, issue = issue_number, "Failed to create issue comment.")
+        })
+    }
+
+    pub async fn create_issue_comment_reaction(
+        &self,
+        repo: &GitHubRepo,
+        comment: &GitHubComment,
+        reaction: &GitHubReaction,
+    ) -> Result<Value> {
+        info!(
+            comment = comment.id,
+            ?reaction,
+            "Creating issue comment reaction."
+        );
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "create_issue_comment_reaction",
+            json!({ "comment": comment.id, "reaction": reaction }),
+        ) {
+            return Ok(json!({ "content": reaction }));
+        }
+        self.post_json(
+            repo,
+            format!(
+                "{}/issues/comments/{}/reactions",
+                self.repo_url(&repo.full_name),
+                comment.id
+            )
+            .as_str(),
+            &HashMap::from([("content", reaction)]),
+            Idempotency::Idempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = %e, comment = comment.id, ?reaction, "Failed to create issue comment reaction.")
+        })
+    }
+
+    pub async fn create_pull_request_review_comment_reaction(
+        &self,
+        repo: &GitHubRepo,
+        comment: &GitHubComment,
+        reaction: &GitHubReaction,
+    ) -> Result<Value> {
+        info!(
+            comment = comment.id,
+            ?reaction,
+            "Creating pull request review comment reaction."
+        );
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "create_pull_request_review_comment_reaction",
+            json!({ "comment": comment.id, "reaction": reaction }),
+        ) {
+            return Ok(json!({ "content": reaction }));
+        }
+        self.post_json(
+            repo,
+            format!(
+                "{}/pulls/comments/{}/reactions",
+                self.repo_url(&repo.full_name),
+                comment.id
+            )
+            .as_str(),
+            &HashMap::from([("content", reaction)]),
+            Idempotency::Idempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = %e, comment = comment.id, ?reaction, "Failed to create pull request review comment reaction.")
+        })
+    }
+
+    /// Pull request reviews have no REST endpoint for reactions, so they're added through GraphQL.
+    pub async fn create_pull_request_review_reaction(
+        &self,
+        repo: &GitHubRepo,
+        review: &GitHubComment,
+        reaction: &GitHubReaction,
+    ) -> Result<Value> {
+        info!(
+            review = review.id,
+            ?reaction,
+            "Creating pull request review reaction."
+        );
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "create_pull_request_review_reaction",
+            json!({ "review": review.id, "reaction": reaction }),
+        ) {
+            return Ok(json!({ "content": reaction.as_graphql_content() }));
+        }
+        let mut variables = Map::new();
+        variables.insert(
+            "input".to_owned(),
+            json!(AddReactionInput {
+                content: reaction.as_graphql_content(),
+                subjectId: review.node_id.to_owned(),
+            }),
+        );
+        let data = self.graphql::<AddReactionResponse>(
+            &repo.full_name,
+            &GraphqlQuery {
+                query: ADD_REACTION_MUTATION.to_owned(),
+                variables: Some(variables),
+            },
+            Idempotency::Idempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = ?e, review = review.id, ?reaction, "Failed to create pull request review reaction.")
+        })?;
+        Ok(json!({ "content": data.addReaction.reaction.content }))
+    }
+
+    /// Reacts to a comment through the endpoint matching its kind.
+    pub async fn create_comment_reaction(
+        &self,
+        repo: &GitHubRepo,
+        kind: GitHubCommentKind,
+        comment: &GitHubComment,
+        reaction: &GitHubReaction,
+    ) -> Result<Value> {
+        match kind {
+            GitHubCommentKind::IssueComment => {
+                self.create_issue_comment_reaction(repo, comment, reaction)
+                    .await
+            }
+            GitHubCommentKind::PullRequestReviewComment => {
+                self.create_pull_request_review_comment_reaction(repo, comment, reaction)
+                    .await
+            }
+            GitHubCommentKind::PullRequestReview => {
+                self.create_pull_request_review_reaction(repo, comment, reaction)
+                    .await
+            }
+        }
+    }
+
+    pub async fn add_labels_to_issue(
+        &self,
+        repo: &GitHubRepo,
+        labels: Vec<&str>,
+        issue_number: u64,
+    ) -> Result<Value> {
+        info!(issue = issue_number, ?labels, "Adding labels to issue.");
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "add_labels_to_issue",
+            json!({ "issue": issue_number, "labels": labels }),
+        ) {
+            return Ok(json!([]));
+        }
+        self.post_json(
+            repo,
+            format!(
+                "{}/issues/{}/labels",
+                self.repo_url(&repo.full_name),
+                issue_number
+            )
+            .as_str(),
+            &HashMap::from([("labels", &labels)]),
+            Idempotency::Idempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = %e, issue = issue_number, ?labels, "Failed to add labels to issue.");
+        })
+    }
+
+    pub async fn request_review(
+        &self,
+        repo: &GitHubRepo,
+        pull_request_number: u64,
+        reviewers: &RequestReviewersDto,
+    ) -> Result<Value> {
+        info!(
+            pr = pull_request_number,
+            ?reviewers,
+            "Requesting reviewers."
+        );
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "request_review",
+            json!({ "pull_request": pull_request_number, "reviewers": reviewers }),
+        ) {
+            return Ok(json!({}));
+        }
+        self.post_json(
+            repo,
+            format!(
+                "{}/pulls/{}/requested_reviewers",
+                self.repo_url(&repo.full_name),
+                pull_request_number
+            )
+            .as_str(),
+            reviewers,
+            Idempotency::Idempotent,
+        )
+        .await
+        .inspect_err(|e| {
+            error!(error = %e, pr = pull_request_number, ?reviewers, "Failed to request reviewers.");
+        })
+    }
+
+    pub async fn minimize_comment(
+        &self,
+        repo: &GitHubRepo,
+        comment: &GitHubComment,
+    ) -> Result<MinimizeCommentResponse> {
+        info!(comment = comment.id, "Minimizing comment.");
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "minimize_comment",
+            json!({ "comment": comment.id }),
+        ) {
+            return Ok(MinimizeCommentResponse {
+                minimizeComment: MinimizeComment {
+                    minimizedComment: MinimizedComment { isMinimized: true },
+                },
+            });
+        }
+        let mut variables = Map::new();
+        variables.insert(
+            "input".to_owned(),
+            json!(MinimizeCommentInput {
+                classifier: ReportedContentClassifier::RESOLVED,
+                clientMutationId: None, // dafuq is this?
+                subjectId: comment.node_id.to_owned(),
+            }),
+        );
+
+        let data = self
+            .graphql::<MinimizeCommentResponse>(
+                &repo.full_name,
+                &GraphqlQuery {
+                    query: MINIMIZE_COMMENT_MUTATION.to_owned(),
+                    variables: Some(variables),
+                },
+                Idempotency::Idempotent,
+            )
+            .await?;
+
+        if data.minimizeComment.minimizedComment.isMinimized {
+            Ok(data)
+        } else {
+            bail!("Failed to minimize comment.")
+        }
+    }
+
+    pub async fn unminimize_comment(
+        &self,
+        repo: &GitHubRepo,
+        comment: &GitHubComment,
+    ) -> Result<UnminimizeCommentResponse> {
+        info!(comment = comment.id, "Unminimizing comment.");
+        if self.skip_in_dry_run(
+            &repo.full_name,
+            "unminimize_comment",
+            json!({ "comment": comment.id }),
+        ) {
+            return Ok(UnminimizeCommentResponse {
+                unminimizeComment: UnminimizeComment {
+                    unminimizedComment: MinimizedComment { isMinimized: false },
+                },
+            });
+        }
+        let mut variables = Map::new();
+        variables.insert(
+            "input".to_owned(),
+            json!(UnminimizeCommentInput {
+                clientMutationId: None, // dafuq is this?
+                subjectId: comment.node_id.to_owned(),
+            }),
+        );
+        let data = self
+            .graphql::<UnminimizeCommentResponse>(
+                &repo.full_name,
+                &GraphqlQuery {
+                    query: UNMINIMIZE_COMMENT_MUTATION.to_owned(),
+                    variables: Some(variables),
+                },
+                Idempotency::Idempotent,
+            )
+            .await
+            .inspect_err(
+                |e| error!(error = ?e, comment = comment.id, "Failed to unminimize comment."),
+            )?;
+
+        if data.unminimizeComment.unminimizedComment.isMinimized {
+            bail!("Failed to unminimize comment.")
+        } else {
+            Ok(data)
+        }
+    }
+
+    async fn authorization(&self, repo: &GitHubRepoId) -> Result<String> {
+        Ok(format!("token {}", self.token(repo).await?))
+    }
+
+    /// Sends the request built by `request`, retrying it according to the retry policy.
+    async fn send(
+        &self,
+        url: &str,
+        idempotency: Idempotency,
+        request: impl Fn() -> RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let response = request().send().await;
+            record_response(url, &response);
+            let failure = match &response {
+                Ok(response) => {
+                    Failure::of_response(response.status(), response.headers(), Utc::now())
+                }
+                Err(err) => Failure::of_error(err),
+            };
+            let Some(delay) =
+                failure.and_then(|failure| self.retry.delay(&failure, idempotency, attempt))
+            else {
+                return response;
+            };
+            warn!(
+                url,
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                status = response
+                    .as_ref()
+                    .ok()
+                    .map(|response| response.status().as_u16()),
+                "Retrying GitHub request."
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    pub async fn get(&self, repo: &GitHubRepo, url: &str) -> Result<Response> {
+        self.get_if_found(&repo.full_name, url)
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to fetch url {}, response status: 404 Not Found",
+                    url
+                )
+            })
+    }
+
+    /// Like [`get`](Self::get), but returns `None` if the resource doesn't exist.
+    pub async fn get_if_found(&self, repo: &GitHubRepoId, url: &str) -> Result<Option<Response>> {
+        let authorization = self.authorization(repo).await?;
+        let response = self
+            .send(url, Idempotency::Idempotent, || {
+                self.http
+                    .get(url)
+                    .header(AUTHORIZATION, authorization.as_str())
+            })
+            .await;
+        match response {
+            Ok(response) if response.status() == StatusCode::NOT_FOUND => Ok(None),
+            Ok(response) if response.status().is_success() => Ok(Some(response)),
+            Ok(err_response) => {
+                error!(response = ?err_response, "Request failed.");
+                bail!(
+                    "Failed to fetch url {}, response status: {}",
+                    url,
+                    err_response.status()
+                )
+            }
+            Err(err) => {
+                error!(error = ?err, "Request failed.");
+                bail!("Failed to fetch url {}, response status: ", url)
+            }
+        }
+    }
+
+    pub async fn post_json<Payload: Serialize, Response: DeserializeOwned>(
+        &self,
+        repo: &GitHubRepo,
+        url: &str,
+        payload: &Payload,
+        idempotency: Idempotency,
+    ) -> Result<Response> {
+        let authorization = self.authorization(&repo.full_name).await?;
+        let response = self
+            .send(url, idempotency, || {
+                self.http
+                    .post(url)
+                    .header(AUTHORIZATION, authorization.as_str())
+                    .json(payload)
+            })
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => Ok(response.json().await?),
+            Ok(err_response) => {
+                error!(response = ?err_response, "Request failed.");
+                bail!(
+                    "Failed to call {}, response status: {}",
+                    url,
+                    err_response.status()
+                );
+            }
+            Err(err) => {
+                error!(error = ?err, "Request failed.");
+                bail!("Failed to call {}", url);
+            }
+        }
+    }
+
+    pub async fn graphql<Data: DeserializeOwned>(
+        &self,
+        repo: &GitHubRepoId,
+        query: &GraphqlQuery,
+        idempotency: Idempotency,
+    ) -> Result<Data, GraphqlError> {
+        let url = self.graphql_url.as_str();
+        let authorization = self
+            .authorization(repo)
+            .await
+            .map_err(GraphqlError::Credentials)?;
+        let result = async {
+            let response = self
+                .send(url, idempotency, || {
+                    self.http
+                        .post(url)
+                        .header(AUTHORIZATION, authorization.as_str())
+                        .json(query)
+                })
+                .await?;
+            let status = response.status();
+            // Data is decoded separately, as it's partial or null when there are errors.
+            match response.json::<GraphqlResponseEnvelope<Value>>().await {
+                Ok(envelope) => {
+                    serde_json::from_value(envelope.ok()?).map_err(GraphqlError::Decode)
+                }
+                // Errors are sent with a JSON body too, e.g. when rate limited, prefer those.
+                Err(_) if !status.is_success() => Err(GraphqlError::Status(status)),
+                Err(err) => Err(GraphqlError::Request(err)),
+            }
+        }
+        .await;
+        result.inspect_err(|e| error!(error = %e, "GraphQL request failed."))
+    }
+}
+
+impl GitHubClient {
+    /// Streams the items of a list endpoint, following `Link: rel="next"` headers until the last
+    /// page.
+    pub fn paginate<'a, T>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        url: &str,
+    ) -> impl Stream<Item = Result<T>> + 'a
+    where
+        T: DeserializeOwned + 'a,
+    {
+        self.paginate_with(repo, url, |page: Vec<T>| page)
+    }
+
+    /// Like [`paginate`](Self::paginate), for endpoints that wrap each page's items in an object.
+    pub fn paginate_with<'a, Page, T>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        url: &str,
+        items: fn(Page) -> Vec<T>,
+    ) -> impl Stream<Item = Result<T>> + 'a
+    where
+        Page: DeserializeOwned + 'a,
+        T: 'a,
+    {
+        stream::try_unfold(Some(paginate::first_page(url)), move |url| async move {
+            let Some(url) = url else {
+                return Ok::<_, anyhow::Error>(None);
+            };
+            let response = self.get(repo, &url).await?;
+            let next = paginate::next_link(response.headers());
+            let page = response.json::<Page>().await?;
+            Ok(Some((items(page), next)))
+        })
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Streams the nodes of a GraphQL connection, passing the end cursor of each page as the
+    /// `$cursor` variable of the next query. `connection` picks the connection out of the response.
+    pub fn paginate_graphql<'a, Data, T>(
+        &'a self,
+        repo: &'a GitHubRepoId,
+        query: &str,
+        variables: Map<String, Value>,
+        connection: fn(Data) -> Connection<T>,
+    ) -> impl Stream<Item = Result<T>> + 'a
+    where
+        Data: DeserializeOwned + 'a,
+        T: 'a,
+    {
+        let query = query.to_owned();
+        stream::try_unfold(Some(Value::Null), move |cursor| {
+            let query = query.clone();
+            let mut variables = variables.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok::<_, anyhow::Error>(None);
+                };
+                variables.insert("cursor".to_owned(), cursor);
+                let data = self
+                    .graphql::<Data>(
+                        repo,
+                        &GraphqlQuery {
+                            query,
+                            variables: Some(variables),
+                        },
+                        Idempotency::Idempotent,
+                    )
+                    .await?;
+                let Connection { nodes, page_info } = connection(data);
+                let next = page_info
+                    .has_next_page
+                    .then_some(page_info.end_cursor)
+                    .flatten()
+                    .map(Value::String);
+                Ok(Some((nodes, next)))
             }
         })
+        .map_ok(|nodes| stream::iter(nodes.into_iter().map(Ok)))
+        .try_flatten()
+    }
+}
+
+// No handler lists anything yet, the endpoints are only exercised by the tests.
+#[cfg_attr(not(test), allow(dead_code))]
+impl GitHubClient {
+    pub fn list_pull_request_files<'a>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        pull_request_number: u64,
+    ) -> impl Stream<Item = Result<GitHubPullRequestFile>> + 'a {
+        self.paginate(
+            repo,
+            &format!(
+                "{}/pulls/{}/files",
+                self.repo_url(&repo.full_name),
+                pull_request_number
+            ),
+        )
+    }
+
+    pub fn list_issue_comments<'a>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        issue_number: u64,
+    ) -> impl Stream<Item = Result<GitHubComment>> + 'a {
+        self.paginate(
+            repo,
+            &format!(
+                "{}/issues/{}/comments",
+                self.repo_url(&repo.full_name),
+                issue_number
+            ),
+        )
+    }
+
+    /// Lists the members of a team of `org`. `repo` is only used to authenticate the requests.
+    pub fn list_team_members<'a>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        org: &str,
+        team_slug: &str,
+    ) -> impl Stream<Item = Result<GitHubUser>> + 'a {
+        self.paginate(
+            repo,
+            &format!("{}/orgs/{}/teams/{}/members", self.api_url, org, team_slug),
+        )
+    }
+
+    pub fn list_labels<'a>(
+        &'a self,
+        repo: &'a GitHubRepo,
+    ) -> impl Stream<Item = Result<GitHubIssueLabel>> + 'a {
+        self.paginate(repo, &format!("{}/labels", self.repo_url(&repo.full_name)))
+    }
+
+    /// Lists the check runs of `git_ref`, which may be a SHA, branch or tag name.
+    pub fn list_check_runs<'a>(
+        &'a self,
+        repo: &'a GitHubRepo,
+        git_ref: &str,
+    ) -> impl Stream<Item = Result<GitHubCheckRun>> + 'a {
+        self.paginate_with(
+            repo,
+            &format!(
+                "{}/commits/{}/check-runs",
+                self.repo_url(&repo.full_name),
+                git_ref
+            ),
+            |page: GitHubCheckRunList| page.check_runs,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+
+    use super::{http_client, GitHubClient, GraphqlError, GraphqlErrorKind};
+    use crate::github::{
+        auth::Credentials,
+        data::{GitHubComment, GitHubPullRequestFileStatus, GitHubRepo, GitHubRepoPermission},
+        paginate::Connection,
+        retry::{Idempotency, RetryPolicy},
+    };
+    use crate::store::{PlannedOperationFilter, Store};
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_partial_json, body_string_contains, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn client(server: &MockServer) -> GitHubClient {
+        GitHubClient::new(
+            http_client(),
+            &format!("{}/api/v3/", server.uri()),
+            &format!("{}/api/graphql", server.uri()),
+            "https://github.example.com/",
+            Credentials::Token("ghp_secret".to_owned()),
+        )
+        .with_retry(RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_secs(1),
+        })
+    }
+
+    fn repo() -> GitHubRepo {
+        serde_json::from_value(json!({ "id": 1, "full_name": "williamboman/mason.nvim" })).unwrap()
+    }
+
+    fn comment_json() -> Value {
+        json!({
+            "id": 42,
+            "node_id": "IC_42",
+            "body": "@williambotman /fixup",
+            "user": { "login": "williamboman", "id": 1 },
+        })
+    }
+
+    fn comment() -> GitHubComment {
+        serde_json::from_value(comment_json()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_call_rest_api_under_base_url() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(
+                "/api/v3/repos/williamboman/mason.nvim/issues/3/comments",
+            ))
+            .and(header("authorization", "token ghp_secret"))
+            .and(body_string_contains("Hello!"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(comment_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let comment = client(&server)
+            .create_issue_comment(&repo(), 3, "Hello!")
+            .await
+            .unwrap();
+        assert_eq!(comment.id, 42);
+    }
+
+    #[tokio::test]
+    async fn it_should_call_graphql_api_at_its_url() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .and(body_partial_json(
+                json!({ "variables": { "input": { "subjectId": "IC_42" } } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "minimizeComment": { "minimizedComment": { "isMinimized": true } } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client(&server)
+            .minimize_comment(&repo(), &comment())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_build_git_urls() {
+        let server = MockServer::start().await;
+        let client = client(&server);
+        assert_eq!(client.git_url(), "https://github.example.com");
+        assert_eq!(
+            client.repo_git_url(&"williamboman/mason.nvim".parse().unwrap()),
+            "https://github.example.com/williamboman/mason.nvim.git"
+        );
+    }
+
+    async fn mount_once(server: &MockServer, http_method: &str, response: ResponseTemplate) {
+        Mock::given(method(http_method))
+            .respond_with(response)
+            .up_to_n_times(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_transient_failures_of_idempotent_requests() {
+        let server = MockServer::start().await;
+        mount_once(&server, "GET", ResponseTemplate::new(502)).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = format!(
+            "{}/api/v3/repos/williamboman/mason.nvim/pulls/1",
+            server.uri()
+        );
+        assert!(client(&server).get(&repo(), &url).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_transient_failures_of_non_idempotent_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(client(&server)
+            .create_issue_comment(&repo(), 3, "Hello!")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_rate_limited_requests() {
+        let server = MockServer::start().await;
+        mount_once(
+            &server,
+            "POST",
+            ResponseTemplate::new(403)
+                .insert_header("x-ratelimit-remaining", "0")
+                .insert_header("retry-after", "0"),
+        )
+        .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(comment_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(client(&server)
+            .create_issue_comment(&repo(), 3, "Hello!")
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_should_give_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let url = format!(
+            "{}/api/v3/repos/williamboman/mason.nvim/issues/3/labels",
+            server.uri()
+        );
+        assert!(client(&server)
+            .post_json::<_, Value>(&repo(), &url, &json!({}), Idempotency::Idempotent)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_not_wait_out_long_rate_limits() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = format!(
+            "{}/api/v3/repos/williamboman/mason.nvim/pulls/1",
+            server.uri()
+        );
+        assert!(client(&server).get(&repo(), &url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_next_links() {
+        let server = MockServer::start().await;
+        let labels_path = "/api/v3/repos/williamboman/mason.nvim/labels";
+        Mock::given(method("GET"))
+            .and(path(labels_path))
+            .and(query_param("per_page", "100"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "link",
+                        format!(
+                            r#"<{}{}?page=2>; rel="next", <{}{}?page=2>; rel="last""#,
+                            server.uri(),
+                            labels_path,
+                            server.uri(),
+                            labels_path
+                        )
+                        .as_str(),
+                    )
+                    .set_body_json(json!([
+                        { "id": 1, "name": "bug", "description": "Something isn't working" },
+                        { "id": 2, "name": "enhancement", "description": null },
+                    ])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(labels_path))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "id": 3, "name": "help wanted", "description": null },
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let labels = client
+            .list_labels(&repo)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            labels
+                .iter()
+                .map(|label| label.name.as_str())
+                .collect::<Vec<_>>(),
+            ["bug", "enhancement", "help wanted"]
+        );
+    }
+
+    /// Serves `first` at `page_path` with a `Link` to a second page serving `second`.
+    async fn mount_two_pages(server: &MockServer, page_path: &str, first: Value, second: Value) {
+        Mock::given(method("GET"))
+            .and(path(page_path))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(second))
+            .expect(1)
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(page_path))
+            .and(query_param("per_page", "100"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "link",
+                        format!(r#"<{}{}?page=2>; rel="next""#, server.uri(), page_path).as_str(),
+                    )
+                    .set_body_json(first),
+            )
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn it_should_list_pull_request_files() {
+        let server = MockServer::start().await;
+        mount_two_pages(
+            &server,
+            "/api/v3/repos/williamboman/mason.nvim/pulls/1/files",
+            json!([{
+                "sha": "abc",
+                "filename": "packages/a/package.yaml",
+                "status": "added",
+                "additions": 10,
+                "deletions": 0,
+                "changes": 10,
+                "patch": "@@ -0,0 +1,10 @@",
+            }]),
+            json!([{
+                "sha": "def",
+                "filename": "packages/c/package.yaml",
+                "status": "renamed",
+                "additions": 0,
+                "deletions": 0,
+                "changes": 0,
+                "previous_filename": "packages/b/package.yaml",
+            }]),
+        )
+        .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let files = client
+            .list_pull_request_files(&repo, 1)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| (file.filename.as_str(), file.status.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "packages/a/package.yaml",
+                    GitHubPullRequestFileStatus::Added
+                ),
+                (
+                    "packages/c/package.yaml",
+                    GitHubPullRequestFileStatus::Renamed
+                ),
+            ]
+        );
+        assert_eq!(
+            files[1].previous_filename.as_deref(),
+            Some("packages/b/package.yaml")
+        );
+        assert_eq!(files[1].patch, None);
+    }
+
+    #[tokio::test]
+    async fn it_should_list_issue_comments() {
+        let server = MockServer::start().await;
+        mount_two_pages(
+            &server,
+            "/api/v3/repos/williamboman/mason.nvim/issues/1/comments",
+            json!([comment_json()]),
+            json!([{
+                "id": 43,
+                "node_id": "IC_43",
+                "body": "Thanks!",
+                "user": { "login": "octocat", "id": 2 },
+            }]),
+        )
+        .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let comments = client
+            .list_issue_comments(&repo, 1)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            comments
+                .iter()
+                .map(|comment| comment.id)
+                .collect::<Vec<_>>(),
+            [42, 43]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_list_team_members() {
+        let server = MockServer::start().await;
+        mount_two_pages(
+            &server,
+            "/api/v3/orgs/mason-org/teams/maintainers/members",
+            json!([{ "login": "williamboman", "id": 1 }]),
+            json!([{ "login": "octocat", "id": 2 }]),
+        )
+        .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let members = client
+            .list_team_members(&repo, "mason-org", "maintainers")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            members
+                .iter()
+                .map(|member| member.login.as_str())
+                .collect::<Vec<_>>(),
+            ["williamboman", "octocat"]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_unwrap_wrapped_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v3/repos/williamboman/mason.nvim/commits/main/check-runs",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "total_count": 1,
+                "check_runs": [{
+                    "id": 7,
+                    "conclusion": "success",
+                    "pull_requests": [],
+                    "started_at": "2024-01-01T00:00:00Z",
+                    "completed_at": "2024-01-01T00:01:00Z",
+                    "status": "completed",
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let check_runs = client
+            .list_check_runs(&repo, "main")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(check_runs.len(), 1);
+        assert_eq!(check_runs[0].id, 7);
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_graphql_cursors() {
+        #[derive(Deserialize)]
+        struct Label {
+            name: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Labels {
+            labels: Connection<Label>,
+        }
+
+        #[derive(Deserialize)]
+        struct Data {
+            repository: Labels,
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .and(body_partial_json(
+                json!({ "variables": { "cursor": null } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "repository": { "labels": {
+                    "nodes": [{ "name": "bug" }],
+                    "pageInfo": { "hasNextPage": true, "endCursor": "Y3Vyc29yOjE=" },
+                } } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .and(body_partial_json(
+                json!({ "variables": { "cursor": "Y3Vyc29yOjE=" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "repository": { "labels": {
+                    "nodes": [{ "name": "enhancement" }],
+                    "pageInfo": { "hasNextPage": false, "endCursor": "Y3Vyc29yOjI=" },
+                } } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let repo = repo();
+        let labels = client
+            .paginate_graphql(
+                &repo.full_name,
+                "query($cursor: String) { repository(owner: \"williamboman\", name: \"mason.nvim\") { labels(first: 100, after: $cursor) { nodes { name } pageInfo { hasNextPage endCursor } } } }",
+                Default::default(),
+                |data: Data| data.repository.labels,
+            )
+            .map_ok(|label| label.name)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(labels, ["bug", "enhancement"]);
+    }
+
+    #[tokio::test]
+    async fn it_should_surface_graphql_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "minimizeComment": null },
+                "errors": [{
+                    "type": "NOT_FOUND",
+                    "path": ["minimizeComment"],
+                    "locations": [{ "line": 3, "column": 5 }],
+                    "message": "Could not resolve to a node with the global id of 'IC_42'",
+                }],
+            })))
+            .mount(&server)
+            .await;
+
+        let err = client(&server)
+            .minimize_comment(&repo(), &comment())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Received GraphQL errors: NOT_FOUND at minimizeComment: Could not resolve to a node with the global id of 'IC_42'"
+        );
+        let graphql_err = GraphqlError::find(&err).unwrap();
+        assert!(graphql_err.is(GraphqlErrorKind::NotFound));
+        assert!(!graphql_err.is(GraphqlErrorKind::Forbidden));
+    }
+
+    #[tokio::test]
+    async fn it_should_recognize_rate_limited_graphql_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "errors": [{ "type": "RATE_LIMITED", "message": "API rate limit exceeded" }],
+            })))
+            .mount(&server)
+            .await;
+
+        let err = client(&server)
+            .unminimize_comment(&repo(), &comment())
+            .await
+            .err()
+            .unwrap();
+        assert!(GraphqlError::find(&err)
+            .unwrap()
+            .is(GraphqlErrorKind::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn it_should_unminimize_comments() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "unminimizeComment": { "unminimizedComment": { "isMinimized": false } } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client(&server)
+            .unminimize_comment(&repo(), &comment())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_record_mutations_in_dry_run_mode() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(comment_json()))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::open(&dir.path().join("botman.db")).unwrap());
+
+        let client = client(&server).with_dry_run(store.clone());
+        let comment = client
+            .create_issue_comment(&repo(), 3, "Hello!")
+            .await
+            .unwrap();
+        assert!(comment.body.unwrap().starts_with("Hello!"));
+        client
+            .add_labels_to_issue(&repo(), vec!["help wanted"], 3)
+            .await
+            .unwrap();
+
+        let operations = store
+            .list_planned_operations(&PlannedOperationFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            operations
+                .iter()
+                .map(|operation| operation.operation.as_str())
+                .collect::<Vec<_>>(),
+            ["add_labels_to_issue", "create_issue_comment"]
+        );
+        assert_eq!(operations[0].repo, "williamboman/mason.nvim");
+        assert_eq!(operations[0].job_id, None);
+        assert_eq!(
+            operations[0].details,
+            r#"{"issue":3,"labels":["help wanted"]}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_look_up_collaborator_permissions() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v3/repos/williamboman/mason.nvim/collaborators/octocat/permission",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "permission": "write",
+                "role_name": "maintain",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v3/repos/williamboman/mason.nvim/collaborators/stranger/permission",
+            ))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        assert_eq!(
+            client
+                .get_collaborator_permission(&repo().full_name, "octocat")
+                .await
+                .unwrap(),
+            GitHubRepoPermission::Maintain
+        );
+        assert_eq!(
+            client
+                .get_collaborator_permission(&repo().full_name, "stranger")
+                .await
+                .unwrap(),
+            GitHubRepoPermission::None
+        );
+    }
 }
@@ -1,5 +1,12 @@
+// GitHub's webhook and API payloads. They mirror GitHub's schema, so fields no handler reads yet
+// are kept, each allowed to be dead on its own.
+
 use anyhow::{anyhow, Result};
-use std::{fmt::Debug, str::FromStr};
+use chrono::{DateTime, Utc};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
 
 use serde::{de, Deserialize, Serialize};
 
@@ -11,17 +18,80 @@ pub struct GitHubUser {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubTeam {
+    #[allow(dead_code)]
     pub id: u64,
+    #[allow(dead_code)]
     pub name: String,
+    #[allow(dead_code)]
     pub slug: String,
 }
 
+/// A collaborator's permission on a repo, from least to most privileged.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum GitHubRepoPermission {
+    None,
+    Read,
+    Triage,
+    Write,
+    Maintain,
+    Admin,
+}
+
+impl Display for GitHubRepoPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Read => "read",
+            Self::Triage => "triage",
+            Self::Write => "write",
+            Self::Maintain => "maintain",
+            Self::Admin => "admin",
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GitHubCollaboratorPermission {
+    /// The legacy permission, which lacks `triage` and `maintain`.
+    pub permission: GitHubRepoPermission,
+    /// The role, which is either one of the permissions or a custom role.
+    pub role_name: Option<String>,
+}
+
+impl GitHubCollaboratorPermission {
+    pub fn effective(&self) -> GitHubRepoPermission {
+        self.role_name
+            .as_ref()
+            .and_then(|role| {
+                serde_json::from_value::<GitHubRepoPermission>(serde_json::Value::String(
+                    role.to_owned(),
+                ))
+                .ok()
+            })
+            .map_or(self.permission, |role| role.max(self.permission))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GitHubTeamMembership {
+    pub state: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitHubInstallation {
+    pub id: u64,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubComment {
     pub id: u64,
     pub node_id: String,
     pub body: Option<String>,
     pub user: GitHubUser,
+    /// Reviews don't have an `updated_at`, only a `submitted_at`.
+    #[serde(alias = "submitted_at")]
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone)]
@@ -51,57 +121,87 @@ impl Debug for GitHubRepoId {
     }
 }
 
+impl Display for GitHubRepoId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
 impl<'de> Deserialize<'de> for GitHubRepoId {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        Ok(String::deserialize(deserializer)
+        String::deserialize(deserializer)
             .map_err(de::Error::custom)?
             .parse()
-            .map_err(de::Error::custom)?)
+            .map_err(de::Error::custom)
     }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubRepo {
+    #[allow(dead_code)]
     pub id: u64,
     pub full_name: GitHubRepoId,
 }
 
-impl GitHubRepo {
-    pub fn as_git_url(&self) -> String {
-        format!(
-            "https://github.com/{}/{}.git",
-            self.full_name.owner, self.full_name.name
-        )
-    }
-
-    pub fn as_api_url(&self) -> String {
-        format!(
-            "https://api.github.com/repos/{}/{}",
-            self.full_name.owner, self.full_name.name
-        )
-    }
-}
-
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubRef {
     pub r#ref: String,
     pub sha: String,
+    #[allow(dead_code)]
     pub user: GitHubUser,
     pub repo: GitHubRepo,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubPullRequest {
+    #[allow(dead_code)]
     pub id: u64,
     pub number: u64,
     pub head: GitHubRef,
     pub base: GitHubRef,
+    /// Missing from the simple pull requests of review and review comment events.
+    #[serde(default)]
     pub merged: bool,
     pub user: GitHubUser,
     pub requested_teams: Vec<GitHubTeam>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GitHubPullRequestFileStatus {
+    Added,
+    Removed,
+    Modified,
+    Renamed,
+    Copied,
+    Changed,
+    Unchanged,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitHubPullRequestFile {
+    #[allow(dead_code)]
+    pub sha: String,
+    #[allow(dead_code)]
+    pub filename: String,
+    #[allow(dead_code)]
+    pub status: GitHubPullRequestFileStatus,
+    #[allow(dead_code)]
+    pub additions: u64,
+    #[allow(dead_code)]
+    pub deletions: u64,
+    #[allow(dead_code)]
+    pub changes: u64,
+    /// Set for renamed files.
+    #[allow(dead_code)]
+    pub previous_filename: Option<String>,
+    /// Missing for binary files and large diffs.
+    #[allow(dead_code)]
+    pub patch: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -141,6 +241,7 @@ pub struct GitHubPullRequestEvent {
     pub action: GitHubPullRequestEventAction,
     pub repository: GitHubRepo,
     pub pull_request: GitHubPullRequest,
+    pub installation: Option<GitHubInstallation>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -179,41 +280,61 @@ pub enum GitHubCheckRunStatus {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubCheckRun {
+    #[allow(dead_code)]
     pub id: u64,
     pub conclusion: Option<GitHubCheckRunConclusion>,
     pub pull_requests: Vec<GitHubCheckRunPullRequest>,
-    pub started_at: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
     pub status: GitHubCheckRunStatus,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubCheckRunRepo {
+    #[allow(dead_code)]
     pub id: u64,
+    #[allow(dead_code)]
     pub name: String,
+    #[allow(dead_code)]
     pub url: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubCheckRunRef {
+    #[allow(dead_code)]
     pub r#ref: String,
+    #[allow(dead_code)]
     pub repo: GitHubCheckRunRepo,
+    #[allow(dead_code)]
     pub sha: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubCheckRunPullRequest {
+    #[allow(dead_code)]
     pub id: u64,
+    #[allow(dead_code)]
     pub number: u64,
     pub url: String,
+    #[allow(dead_code)]
     pub base: GitHubCheckRunRef,
+    #[allow(dead_code)]
     pub head: GitHubCheckRunRef,
 }
 
+/// The check runs of a ref, as listed by `GET /repos/{owner}/{repo}/commits/{ref}/check-runs`.
+#[derive(Deserialize, Debug)]
+pub struct GitHubCheckRunList {
+    pub check_runs: Vec<GitHubCheckRun>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GitHubCheckRunEvent {
+    #[allow(dead_code)]
     pub action: GitHubCheckRunEventAction,
     pub repository: GitHubRepo,
     pub check_run: GitHubCheckRun,
+    pub installation: Option<GitHubInstallation>,
 }
 
 #[derive(Debug)]
@@ -221,9 +342,63 @@ pub enum GitHubWebhook {
     IssueComment(GitHubIssueCommentEvent),
     Issues(GitHubIssuesEvent),
     PullRequest(GitHubPullRequestEvent),
+    PullRequestReview(GitHubPullRequestReview),
+    PullRequestReviewComment(GitHubPullRequestReviewComment),
     CheckRun(GitHubCheckRunEvent),
 }
 
+impl GitHubWebhook {
+    pub fn repository(&self) -> &GitHubRepo {
+        match self {
+            GitHubWebhook::IssueComment(event) => &event.repository,
+            GitHubWebhook::Issues(event) => &event.repository,
+            GitHubWebhook::PullRequest(event) => &event.repository,
+            GitHubWebhook::PullRequestReview(event) => &event.repository,
+            GitHubWebhook::PullRequestReviewComment(event) => &event.repository,
+            GitHubWebhook::CheckRun(event) => &event.repository,
+        }
+    }
+
+    /// When the object that triggered the webhook was last changed, used to detect replays of old
+    /// deliveries.
+    pub fn occurred_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            GitHubWebhook::IssueComment(event) => event.comment.updated_at,
+            GitHubWebhook::Issues(event) => Some(event.issue.updated_at),
+            GitHubWebhook::PullRequest(event) => Some(event.pull_request.updated_at),
+            GitHubWebhook::PullRequestReview(event) => event.review.updated_at,
+            GitHubWebhook::PullRequestReviewComment(event) => event.comment.updated_at,
+            GitHubWebhook::CheckRun(event) => event
+                .check_run
+                .completed_at
+                .or(Some(event.check_run.started_at)),
+        }
+    }
+
+    /// The number of the issue or pull request the webhook concerns, if any.
+    pub fn issue_number(&self) -> Option<u64> {
+        match self {
+            GitHubWebhook::IssueComment(event) => Some(event.issue.number),
+            GitHubWebhook::Issues(event) => Some(event.issue.number),
+            GitHubWebhook::PullRequest(event) => Some(event.pull_request.number),
+            GitHubWebhook::PullRequestReview(event) => Some(event.pull_request.number),
+            GitHubWebhook::PullRequestReviewComment(event) => Some(event.pull_request.number),
+            GitHubWebhook::CheckRun(_) => None,
+        }
+    }
+
+    pub fn installation(&self) -> Option<&GitHubInstallation> {
+        match self {
+            GitHubWebhook::IssueComment(event) => event.installation.as_ref(),
+            GitHubWebhook::Issues(event) => event.installation.as_ref(),
+            GitHubWebhook::PullRequest(event) => event.installation.as_ref(),
+            GitHubWebhook::PullRequestReview(event) => event.installation.as_ref(),
+            GitHubWebhook::PullRequestReviewComment(event) => event.installation.as_ref(),
+            GitHubWebhook::CheckRun(event) => event.installation.as_ref(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum GitHubPullRequestReviewCommentAction {
@@ -237,6 +412,8 @@ pub struct GitHubPullRequestReviewComment {
     pub action: GitHubPullRequestReviewCommentAction,
     pub comment: GitHubComment,
     pub pull_request: GitHubPullRequest,
+    pub repository: GitHubRepo,
+    pub installation: Option<GitHubInstallation>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -252,6 +429,27 @@ pub struct GitHubPullRequestReview {
     pub action: GitHubPullRequestReviewAction,
     pub review: GitHubComment,
     pub pull_request: GitHubPullRequest,
+    pub repository: GitHubRepo,
+    pub installation: Option<GitHubInstallation>,
+}
+
+/// The kinds of comments that commands can be issued from. Each kind has its own reaction and
+/// minimization endpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GitHubCommentKind {
+    IssueComment,
+    PullRequestReviewComment,
+    PullRequestReview,
+}
+
+impl GitHubCommentKind {
+    /// Pull request reviews can't be minimized, only (review) comments can.
+    pub fn is_minimizable(&self) -> bool {
+        match self {
+            GitHubCommentKind::IssueComment | GitHubCommentKind::PullRequestReviewComment => true,
+            GitHubCommentKind::PullRequestReview => false,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -284,6 +482,22 @@ impl Serialize for GitHubReaction {
     }
 }
 
+impl GitHubReaction {
+    /// The `ReactionContent` enum value used by the GraphQL API.
+    pub fn as_graphql_content(&self) -> &'static str {
+        match self {
+            GitHubReaction::PlusOne => "THUMBS_UP",
+            GitHubReaction::MinusOne => "THUMBS_DOWN",
+            GitHubReaction::Laugh => "LAUGH",
+            GitHubReaction::Confused => "CONFUSED",
+            GitHubReaction::Heart => "HEART",
+            GitHubReaction::Hooray => "HOORAY",
+            GitHubReaction::Rocket => "ROCKET",
+            GitHubReaction::Eyes => "EYES",
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum GitHubIssueCommentEventAction {
@@ -298,9 +512,10 @@ pub struct GitHubIssueCommentEvent {
     pub issue: GitHubIssue,
     pub comment: GitHubComment,
     pub repository: GitHubRepo,
+    pub installation: Option<GitHubInstallation>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum GitHubIssuesEventAction {
     Opened,
@@ -325,8 +540,12 @@ pub enum GitHubIssuesEventAction {
 pub struct GitHubIssuesEvent {
     pub action: GitHubIssuesEventAction,
     pub issue: GitHubIssue,
+    /// The label that was added or removed, for `labeled` and `unlabeled` events.
+    pub label: Option<GitHubIssueLabel>,
     pub repository: GitHubRepo,
+    #[allow(dead_code)]
     pub sender: GitHubUser,
+    pub installation: Option<GitHubInstallation>,
 }
 
 #[derive(PartialEq, Deserialize, Debug)]
@@ -339,28 +558,40 @@ pub struct GitHubIssuePullRequest {
 #[serde(rename_all = "snake_case")]
 pub enum GitHubIssueState {
     Open,
+    Closed,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct GitHubIssueLabel {
+    #[allow(dead_code)]
     pub id: u64,
     pub name: String,
-    pub description: String,
+    #[allow(dead_code)]
+    pub description: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct GitHubIssue {
+    #[allow(dead_code)]
     pub id: u64,
+    pub node_id: String,
     pub number: u64,
+    #[allow(dead_code)]
     pub user: GitHubUser,
+    #[allow(dead_code)]
     pub title: String,
+    #[allow(dead_code)]
     pub body: Option<String>,
+    #[allow(dead_code)]
     pub assignees: Vec<GitHubUser>,
+    #[allow(dead_code)]
     pub locked: bool,
+    #[allow(dead_code)]
     pub comments: u64,
     pub labels: Vec<GitHubIssueLabel>,
     pub state: GitHubIssueState,
     pub pull_request: Option<GitHubIssuePullRequest>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl GitHubIssue {
@@ -1,4 +1,8 @@
 pub mod action;
+pub mod auth;
 pub mod client;
 pub mod data;
+pub mod paginate;
+pub mod projects;
+pub mod retry;
 pub mod webhook;
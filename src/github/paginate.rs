@@ -0,0 +1,107 @@
+use reqwest::header::{HeaderMap, LINK};
+use serde::Deserialize;
+
+/// How many items to request per page, the maximum most list endpoints allow.
+pub const PER_PAGE: u32 = 100;
+
+/// Finds the URL of the next page in a `Link` header, e.g.
+/// `<https://api.github.com/repositories/1/labels?page=2>; rel="next", <…>; rel="last"`.
+pub fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let mut parts = link.split(';');
+            let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+            parts
+                .any(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("rel=")
+                        .map(|rel| rel.trim_matches('"').split(' ').any(|rel| rel == "next"))
+                        .unwrap_or(false)
+                })
+                .then(|| url.to_owned())
+        })
+}
+
+/// Requests the first page of `url` with as many items as possible.
+pub fn first_page(url: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}per_page={}", url, separator, PER_PAGE)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+}
+
+/// A page of a GraphQL connection. Queries select `nodes` and `pageInfo { hasNextPage endCursor }`
+/// and take an `$cursor: String` variable to pass as `after`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection<T> {
+    pub nodes: Vec<T>,
+    pub page_info: PageInfo,
+}
+
+impl<T> Default for Connection<T> {
+    /// An empty last page, for when the connection is missing from a response.
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            page_info: PageInfo {
+                has_next_page: false,
+                end_cursor: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, LINK};
+
+    use super::{first_page, next_link};
+
+    #[test]
+    fn it_should_find_next_link() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_link(&headers), None);
+
+        headers.insert(
+            LINK,
+            r#"<https://api.github.com/repositories/1/labels?page=1>; rel="prev", <https://api.github.com/repositories/1/labels?page=3>; rel="next", <https://api.github.com/repositories/1/labels?page=5>; rel="last""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            next_link(&headers).as_deref(),
+            Some("https://api.github.com/repositories/1/labels?page=3")
+        );
+
+        headers.insert(
+            LINK,
+            r#"<https://api.github.com/repositories/1/labels?page=1>; rel="first""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(next_link(&headers), None);
+    }
+
+    #[test]
+    fn it_should_request_full_pages() {
+        assert_eq!(
+            first_page("https://api.github.com/repos/o/r/labels"),
+            "https://api.github.com/repos/o/r/labels?per_page=100"
+        );
+        assert_eq!(
+            first_page("https://api.github.com/repos/o/r/issues?state=open"),
+            "https://api.github.com/repos/o/r/issues?state=open&per_page=100"
+        );
+    }
+}
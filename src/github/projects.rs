@@ -0,0 +1,496 @@
+use std::{collections::HashMap, pin::pin, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use futures::{future, TryStreamExt};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::config::{self, ProjectConfig, ProjectStatus};
+
+use super::{
+    client::{GitHubClient, GraphqlQuery},
+    data::{GitHubIssueState, GitHubIssuesEvent, GitHubIssuesEventAction, GitHubRepoId},
+    paginate::Connection,
+    retry::Idempotency,
+};
+
+const PROJECTS_QUERY: &str = r#"
+query($owner: String!, $title: String!, $field: String!, $cursor: String) {
+    repositoryOwner(login: $owner) {
+        ... on ProjectV2Owner {
+            projectsV2(first: 20, query: $title, after: $cursor) {
+                nodes {
+                    id
+                    title
+                    field(name: $field) {
+                        ... on ProjectV2SingleSelectField {
+                            id
+                            options {
+                                id
+                                name
+                            }
+                        }
+                    }
+                }
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+            }
+        }
+    }
+}
+"#;
+
+const ADD_ITEM_MUTATION: &str = r#"
+mutation($project: ID!, $content: ID!) {
+    addProjectV2ItemById(input: { projectId: $project, contentId: $content }) {
+        item {
+            id
+        }
+    }
+}
+"#;
+
+const SET_STATUS_MUTATION: &str = r#"
+mutation($project: ID!, $item: ID!, $field: ID!, $option: String!) {
+    updateProjectV2ItemFieldValue(
+        input: { projectId: $project, itemId: $item, fieldId: $field, value: { singleSelectOptionId: $option } }
+    ) {
+        projectV2Item {
+            id
+        }
+    }
+}
+"#;
+
+lazy_static! {
+    /// Resolved projects by owner, title and status field, as they don't change while running.
+    static ref PROJECTS: Mutex<HashMap<(String, String, String), Arc<Project>>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectsData {
+    repository_owner: Option<ProjectOwner>,
+}
+
+/// Owners that can't have projects come back as an empty object.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectOwner {
+    projects_v2: Option<Connection<ProjectNode>>,
+}
+
+#[derive(Deserialize)]
+struct ProjectNode {
+    id: String,
+    title: String,
+    field: Option<StatusFieldNode>,
+}
+
+/// Fields other than single select ones come back as an empty object.
+#[derive(Deserialize)]
+struct StatusFieldNode {
+    id: Option<String>,
+    options: Option<Vec<StatusOptionNode>>,
+}
+
+#[derive(Deserialize)]
+struct StatusOptionNode {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddedItem {
+    add_project_v2_item_by_id: AddedItemPayload,
+}
+
+#[derive(Deserialize)]
+struct AddedItemPayload {
+    item: ItemNode,
+}
+
+#[derive(Deserialize)]
+struct ItemNode {
+    id: String,
+}
+
+/// A Projects V2 board, with the IDs of its status field and the field's options.
+#[derive(Debug)]
+pub struct Project {
+    pub id: String,
+    pub status_field_id: String,
+    /// Option IDs by name.
+    pub status_options: HashMap<String, String>,
+}
+
+impl Project {
+    fn status_option(&self, config: &ProjectConfig, status: ProjectStatus) -> Result<&str> {
+        let name = config.option_name(status);
+        self.status_options
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} has no {} option named {}.",
+                    config.title,
+                    config.status_field,
+                    name
+                )
+            })
+    }
+}
+
+fn variables(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(variables) => variables,
+        _ => Map::new(),
+    }
+}
+
+/// Looks up the project and its status field by name, authenticating as `repo`.
+async fn find(
+    client: &GitHubClient,
+    repo: &GitHubRepoId,
+    config: &ProjectConfig,
+) -> Result<Project> {
+    let projects = client.paginate_graphql(
+        repo,
+        PROJECTS_QUERY,
+        variables(json!({
+            "owner": config.owner,
+            "title": config.title,
+            "field": config.status_field,
+        })),
+        |data: ProjectsData| {
+            data.repository_owner
+                .and_then(|owner| owner.projects_v2)
+                .unwrap_or_default()
+        },
+    );
+    // The query matches titles loosely, pick the exact match.
+    let project = pin!(projects.try_filter(|project| future::ready(project.title == config.title)))
+        .try_next()
+        .await?
+        .ok_or_else(|| anyhow!("{} has no project titled {}.", config.owner, config.title))?;
+    let (Some(status_field_id), Some(options)) = project
+        .field
+        .map_or((None, None), |field| (field.id, field.options))
+    else {
+        return Err(anyhow!(
+            "{} has no single select field named {}.",
+            config.title,
+            config.status_field
+        ));
+    };
+    Ok(Project {
+        id: project.id,
+        status_field_id,
+        status_options: options
+            .into_iter()
+            .map(|option| (option.name, option.id))
+            .collect(),
+    })
+}
+
+/// Returns the project of `config`, looking it up on first use.
+pub async fn resolve(
+    client: &GitHubClient,
+    repo: &GitHubRepoId,
+    config: &ProjectConfig,
+) -> Result<Arc<Project>> {
+    let key = (
+        config.owner.clone(),
+        config.title.clone(),
+        config.status_field.clone(),
+    );
+    let mut projects = PROJECTS.lock().await;
+    if let Some(project) = projects.get(&key) {
+        return Ok(project.clone());
+    }
+    let project = Arc::new(find(client, repo, config).await?);
+    info!(owner = %config.owner, title = %config.title, id = %project.id, "Resolved project.");
+    projects.insert(key, project.clone());
+    Ok(project)
+}
+
+/// Resolves the projects of all configured repos, so that failing lookups show up on startup.
+pub async fn resolve_all(client: &GitHubClient) {
+    for (repo, repo_config) in &config::get().repos {
+        let (Ok(repo_id), Some(project)) = (repo.parse::<GitHubRepoId>(), &repo_config.project)
+        else {
+            continue;
+        };
+        if let Err(err) = resolve(client, &repo_id, project).await {
+            error!(error = %err, %repo, "Failed to resolve project.");
+        }
+    }
+}
+
+/// The status an issue event moves the issue to, if any. A label mapped to a status wins over the
+/// event's action: the added label for `labeled` events, any label for `opened` ones. Closed
+/// issues stay closed when they're labeled.
+pub fn status_for_event(
+    config: &ProjectConfig,
+    event: &GitHubIssuesEvent,
+) -> Option<ProjectStatus> {
+    let labels = match (&event.action, &event.issue.state) {
+        (_, GitHubIssueState::Closed) => vec![],
+        (GitHubIssuesEventAction::Opened, _) => event.issue.labels.iter().collect(),
+        (GitHubIssuesEventAction::Labeled, _) => event.label.iter().collect(),
+        _ => vec![],
+    };
+    labels
+        .into_iter()
+        .find_map(|label| config.labels.get(&label.name))
+        .or_else(|| config.actions.get(&event.action))
+        .copied()
+}
+
+/// Adds an issue or pull request to the project of `config`, if it isn't already, and sets its
+/// status.
+pub async fn set_status(
+    client: &GitHubClient,
+    repo: &GitHubRepoId,
+    config: &ProjectConfig,
+    content_node_id: &str,
+    status: ProjectStatus,
+) -> Result<()> {
+    info!(%repo, content = content_node_id, ?status, "Setting project status.");
+    if client.skip_in_dry_run(
+        repo,
+        "set_project_status",
+        json!({ "project": config.title, "content": content_node_id, "status": config.option_name(status) }),
+    ) {
+        return Ok(());
+    }
+    async {
+        let project = resolve(client, repo, config).await?;
+        let option = project.status_option(config, status)?;
+        let added = client
+            .graphql::<AddedItem>(
+                repo,
+                &GraphqlQuery::new(
+                    ADD_ITEM_MUTATION,
+                    variables(json!({ "project": project.id, "content": content_node_id })),
+                ),
+                Idempotency::Idempotent,
+            )
+            .await?;
+        client
+            .graphql::<Value>(
+                repo,
+                &GraphqlQuery::new(
+                    SET_STATUS_MUTATION,
+                    variables(json!({
+                        "project": project.id,
+                        "item": added.add_project_v2_item_by_id.item.id,
+                        "field": project.status_field_id,
+                        "option": option,
+                    })),
+                ),
+                Idempotency::Idempotent,
+            )
+            .await?;
+        Ok(())
+    }
+    .await
+    .inspect_err(|e: &anyhow::Error| {
+        error!(error = %e, %repo, content = content_node_id, ?status, "Failed to set project status.")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, body_string_contains, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{set_status, status_for_event};
+    use crate::{
+        config::{default_project_actions, ProjectConfig, ProjectStatus},
+        github::{
+            auth::Credentials,
+            client::{http_client, GitHubClient},
+            data::{GitHubIssueState, GitHubIssuesEvent},
+        },
+    };
+
+    fn project_config(title: &str) -> ProjectConfig {
+        ProjectConfig {
+            owner: "williamboman".to_owned(),
+            title: title.to_owned(),
+            status_field: "Status".to_owned(),
+            statuses: BTreeMap::from([(ProjectStatus::Support, "Needs support".to_owned())]),
+            actions: default_project_actions(),
+            labels: BTreeMap::from([
+                ("question".to_owned(), ProjectStatus::Support),
+                ("new-package-request".to_owned(), ProjectStatus::Prioritized),
+            ]),
+        }
+    }
+
+    fn issue_event(action: &str, labels: &[&str], label: Option<&str>) -> GitHubIssuesEvent {
+        let label_json = |name: &str| json!({ "id": 1, "name": name, "description": null });
+        serde_json::from_value(json!({
+            "action": action,
+            "issue": {
+                "id": 1,
+                "node_id": "I_1",
+                "number": 1,
+                "user": { "id": 1, "login": "octocat" },
+                "title": "Add package",
+                "body": null,
+                "assignees": [],
+                "locked": false,
+                "comments": 0,
+                "labels": labels.iter().map(|name| label_json(name)).collect::<Vec<_>>(),
+                "state": "open",
+                "pull_request": null,
+                "updated_at": "2024-01-01T00:00:00Z",
+            },
+            "label": label.map(label_json),
+            "repository": { "id": 1, "full_name": "williamboman/mason.nvim" },
+            "sender": { "id": 1, "login": "octocat" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_should_map_issue_events_to_statuses() {
+        let config = project_config("mason.nvim");
+        let status = |action, labels: &[&str], label| {
+            status_for_event(&config, &issue_event(action, labels, label))
+        };
+
+        assert_eq!(status("opened", &[], None), Some(ProjectStatus::Triage));
+        assert_eq!(
+            status("opened", &["new-package-request"], None),
+            Some(ProjectStatus::Prioritized)
+        );
+        assert_eq!(
+            status("closed", &["question"], None),
+            Some(ProjectStatus::Closed)
+        );
+        assert_eq!(status("reopened", &[], None), Some(ProjectStatus::Triage));
+        assert_eq!(
+            status("labeled", &["bug", "question"], Some("question")),
+            Some(ProjectStatus::Support)
+        );
+        assert_eq!(status("labeled", &["question", "bug"], Some("bug")), None);
+        assert_eq!(status("edited", &["question"], None), None);
+
+        let mut closed = issue_event("labeled", &["question"], Some("question"));
+        closed.issue.state = GitHubIssueState::Closed;
+        assert_eq!(status_for_event(&config, &closed), None);
+    }
+
+    async fn mock_projects(server: &MockServer, title: &str) {
+        Mock::given(method("POST"))
+            .and(body_string_contains("projectsV2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "repositoryOwner": { "projectsV2": {
+                    "nodes": [
+                        { "id": "PVT_other", "title": format!("{} (archive)", title), "field": {} },
+                        {
+                            "id": "PVT_1",
+                            "title": title,
+                            "field": {
+                                "id": "PVTSSF_1",
+                                "options": [
+                                    { "id": "opt_triage", "name": "Triage" },
+                                    { "id": "opt_support", "name": "Needs support" },
+                                ],
+                            },
+                        },
+                    ],
+                    "pageInfo": { "hasNextPage": false, "endCursor": null },
+                } } }
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn client(server: &MockServer) -> GitHubClient {
+        GitHubClient::new(
+            http_client(),
+            &server.uri(),
+            &server.uri(),
+            "https://github.example.com",
+            Credentials::Token("ghp_secret".to_owned()),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_should_add_items_and_set_their_status() {
+        let server = MockServer::start().await;
+        mock_projects(&server, "mason.nvim").await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "variables": { "project": "PVT_1", "content": "I_1" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "addProjectV2ItemById": { "item": { "id": "PVTI_1" } } }
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        for option in ["opt_triage", "opt_support"] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(json!({ "variables": {
+                    "project": "PVT_1",
+                    "item": "PVTI_1",
+                    "field": "PVTSSF_1",
+                    "option": option,
+                } })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "updateProjectV2ItemFieldValue": { "projectV2Item": { "id": "PVTI_1" } } }
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let client = client(&server);
+        let repo = "williamboman/mason.nvim".parse().unwrap();
+        let config = project_config("mason.nvim");
+        // The project is only looked up once.
+        set_status(&client, &repo, &config, "I_1", ProjectStatus::Triage)
+            .await
+            .unwrap();
+        set_status(&client, &repo, &config, "I_1", ProjectStatus::Support)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_on_unknown_status_options() {
+        let server = MockServer::start().await;
+        mock_projects(&server, "mason-registry").await;
+
+        let err = set_status(
+            &client(&server),
+            &"williamboman/mason.nvim".parse().unwrap(),
+            &project_config("mason-registry"),
+            "I_1",
+            ProjectStatus::Backlog,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "mason-registry has no Status option named Backlog."
+        );
+    }
+}
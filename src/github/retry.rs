@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use rand::Rng;
+use reqwest::{header::HeaderMap, StatusCode};
+
+use crate::config::RetryConfig;
+
+/// Whether a request may be repeated without changing its outcome. Only idempotent requests are
+/// retried after failures GitHub may already have acted on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Idempotency {
+    Idempotent,
+    NonIdempotent,
+}
+
+/// Why a request failed, if it's worth retrying.
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    /// GitHub turned the request away without acting on it (rate limits, connection failures), so
+    /// any request may be retried. Rate limits tell how long to wait.
+    Rejected { wait: Option<Duration> },
+    /// The request may or may not have been acted on (5xx responses, timeouts).
+    Transient,
+}
+
+impl Failure {
+    /// Classifies a response by its status and rate limit headers.
+    pub fn of_response(
+        status: StatusCode,
+        headers: &HeaderMap,
+        now: DateTime<Utc>,
+    ) -> Option<Failure> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let retry_after = header("retry-after")
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let exhausted = header("x-ratelimit-remaining") == Some("0");
+        let reset_in = header("x-ratelimit-reset")
+            .and_then(|value| value.parse::<i64>().ok())
+            .and_then(|reset| Utc.timestamp_opt(reset, 0).single())
+            // The reset time has a resolution of seconds, wait for one more to be safe.
+            .map(|reset| (reset - now).to_std().unwrap_or_default() + Duration::from_secs(1));
+
+        match status {
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if retry_after.is_some() => {
+                Some(Failure::Rejected { wait: retry_after })
+            }
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if exhausted => {
+                Some(Failure::Rejected { wait: reset_in })
+            }
+            StatusCode::TOO_MANY_REQUESTS => Some(Failure::Rejected { wait: None }),
+            StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Some(Failure::Transient),
+            _ => None,
+        }
+    }
+
+    /// Classifies a request that failed without a response.
+    pub fn of_error(err: &reqwest::Error) -> Option<Failure> {
+        if err.is_connect() {
+            Some(Failure::Rejected { wait: None })
+        } else if err.is_timeout() || err.is_request() {
+            Some(Failure::Transient)
+        } else {
+            None
+        }
+    }
+}
+
+/// Retries failed requests with jittered exponential backoff, or as long as a rate limit asks for.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts,
+            initial_delay: Duration::from_millis(config.initial_delay_ms),
+            max_delay: Duration::from_secs(config.max_delay_secs),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        (&RetryConfig::default()).into()
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before making another attempt after the `attempt`th one failed, if at all.
+    pub fn delay(
+        &self,
+        failure: &Failure,
+        idempotency: Idempotency,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match failure {
+            Failure::Transient if idempotency == Idempotency::NonIdempotent => None,
+            // Waiting out a rate limit that resets far in the future would stall the job, better
+            // fail right away.
+            Failure::Rejected { wait: Some(wait) } => (*wait <= self.max_delay).then_some(*wait),
+            Failure::Rejected { wait: None } | Failure::Transient => Some(self.backoff(attempt)),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        // Equal jitter: at least half the delay, so that retries still back off.
+        delay / 2 + delay.mul_f64(rand::thread_rng().gen_range(0.0..=0.5))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use reqwest::{header::HeaderMap, StatusCode};
+
+    use super::{Failure, Idempotency, RetryPolicy};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn it_should_classify_responses() {
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let failure = |status, pairs| Failure::of_response(status, &headers(pairs), now);
+
+        assert_eq!(failure(StatusCode::OK, &[]), None);
+        assert_eq!(failure(StatusCode::NOT_FOUND, &[]), None);
+        assert_eq!(failure(StatusCode::FORBIDDEN, &[]), None);
+        assert_eq!(
+            failure(StatusCode::BAD_GATEWAY, &[]),
+            Some(Failure::Transient)
+        );
+        assert_eq!(
+            failure(StatusCode::FORBIDDEN, &[("retry-after", "30")]),
+            Some(Failure::Rejected {
+                wait: Some(Duration::from_secs(30))
+            })
+        );
+        assert_eq!(
+            failure(
+                StatusCode::FORBIDDEN,
+                &[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700000009")
+                ]
+            ),
+            Some(Failure::Rejected {
+                wait: Some(Duration::from_secs(10))
+            })
+        );
+        assert_eq!(
+            failure(StatusCode::TOO_MANY_REQUESTS, &[]),
+            Some(Failure::Rejected { wait: None })
+        );
+    }
+
+    #[test]
+    fn it_should_only_retry_transient_failures_of_idempotent_requests() {
+        let policy = policy();
+        assert!(policy
+            .delay(&Failure::Transient, Idempotency::Idempotent, 1)
+            .is_some());
+        assert!(policy
+            .delay(&Failure::Transient, Idempotency::NonIdempotent, 1)
+            .is_none());
+        assert!(policy
+            .delay(
+                &Failure::Rejected { wait: None },
+                Idempotency::NonIdempotent,
+                1
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn it_should_give_up() {
+        let policy = policy();
+        assert!(policy
+            .delay(&Failure::Transient, Idempotency::Idempotent, 4)
+            .is_none());
+        assert!(policy
+            .delay(
+                &Failure::Rejected {
+                    wait: Some(Duration::from_secs(3600))
+                },
+                Idempotency::Idempotent,
+                1
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn it_should_back_off_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 20,
+            ..policy()
+        };
+        for (attempt, max) in [(1, 100), (2, 200), (3, 400), (10, 1000)] {
+            let delay = policy
+                .delay(&Failure::Transient, Idempotency::Idempotent, attempt)
+                .unwrap();
+            let max = Duration::from_millis(max);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::anyhow;
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    Request,
+};
+
+/// The `X-GitHub-Delivery` GUID of an incoming webhook. Redeliveries reuse the ID of the
+/// original delivery, which is how duplicates are recognized by the [`crate::store::Store`].
+#[derive(Debug)]
+pub struct GitHubDelivery(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for GitHubDelivery {
+    type Error = anyhow::Error;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.headers().get_one("X-GitHub-Delivery") {
+            Some(id) if !id.is_empty() => request::Outcome::Success(GitHubDelivery(id.to_owned())),
+            _ => request::Outcome::Error((
+                Status::BadRequest,
+                anyhow!("Missing X-GitHub-Delivery header."),
+            )),
+        }
+    }
+}
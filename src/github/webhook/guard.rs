@@ -7,20 +7,44 @@ use rocket::{
 };
 use serde::Deserialize;
 
+use sha1::Sha1;
 use sha2::Sha256;
 use std::str::FromStr;
 
 use crate::{
-    github::data::{
-        GitHubCheckRunEvent, GitHubIssueCommentEvent, GitHubIssuesEvent, GitHubPullRequestEvent,
-        GitHubWebhook,
+    config::{self, Secret},
+    github::{
+        client::GitHubClient,
+        data::{
+            GitHubCheckRunEvent, GitHubIssueCommentEvent, GitHubIssuesEvent,
+            GitHubPullRequestEvent, GitHubPullRequestReview, GitHubPullRequestReviewComment,
+            GitHubWebhook,
+        },
     },
-    GITHUB_WEBHOOK_SECRET,
+    metrics,
 };
 
+#[derive(Debug, PartialEq)]
+pub enum SignatureAlgorithm {
+    Sha256,
+    Sha1,
+}
+
+impl FromStr for SignatureAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(Self::Sha256),
+            "sha1" => Ok(Self::Sha1),
+            prefix => bail!("Unsupported signature prefix {}.", prefix),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GitHubSignature {
-    pub prefix: String,
+    pub algorithm: SignatureAlgorithm,
     pub payload: Vec<u8>,
 }
 
@@ -28,9 +52,9 @@ impl FromStr for GitHubSignature {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some((prefix, payload)) = s.split_once("=") {
+        if let Some((prefix, payload)) = s.split_once('=') {
             Ok(Self {
-                prefix: prefix.to_owned(),
+                algorithm: prefix.parse()?,
                 payload: hex::decode(payload)?,
             })
         } else {
@@ -39,8 +63,77 @@ impl FromStr for GitHubSignature {
     }
 }
 
+impl GitHubSignature {
+    fn verify(&self, secret: &Secret, payload: &[u8]) -> bool {
+        let secret = secret.expose().as_bytes();
+        match self.algorithm {
+            SignatureAlgorithm::Sha256 => {
+                let mut hmac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("Failed to create hmac.");
+                hmac.update(payload);
+                hmac.verify_slice(&self.payload).is_ok()
+            }
+            SignatureAlgorithm::Sha1 => {
+                let mut hmac =
+                    Hmac::<Sha1>::new_from_slice(secret).expect("Failed to create hmac.");
+                hmac.update(payload);
+                hmac.verify_slice(&self.payload).is_ok()
+            }
+        }
+    }
+}
+
+/// Verifies the payload against the `X-Hub-Signature-256` header, falling back to the legacy
+/// `X-Hub-Signature` (HMAC-SHA1) header only if `allow_sha1` is set. The signature is accepted if it
+/// matches any of the `secrets`.
+fn verify_signature(
+    sha256_header: Option<&str>,
+    sha1_header: Option<&str>,
+    payload: &[u8],
+    secrets: &[&Secret],
+    allow_sha1: bool,
+) -> Result<(), (Status, anyhow::Error)> {
+    let (header, expected_algorithm) = match (sha256_header, sha1_header) {
+        (Some(header), _) => (header, SignatureAlgorithm::Sha256),
+        (None, Some(header)) if allow_sha1 => (header, SignatureAlgorithm::Sha1),
+        _ => return Err((Status::Unauthorized, anyhow!("Bad or missing signature."))),
+    };
+
+    let signature = header
+        .parse::<GitHubSignature>()
+        .map_err(|err| (Status::Unauthorized, anyhow!("Bad signature: {}", err)))?;
+
+    if signature.algorithm != expected_algorithm {
+        return Err((
+            Status::Unauthorized,
+            anyhow!(
+                "Signature prefix {:?} doesn't match its header.",
+                signature.algorithm
+            ),
+        ));
+    }
+
+    if secrets
+        .iter()
+        .any(|secret| signature.verify(secret, payload))
+    {
+        Ok(())
+    } else {
+        Err((Status::Forbidden, anyhow!("Mismatching signature.")))
+    }
+}
+
+/// A webhook whose signature has been verified, along with the raw event name and payload it was
+/// parsed from so that it can be persisted and parsed again later.
+#[derive(Debug)]
+pub struct VerifiedWebhook {
+    pub event: String,
+    pub payload: String,
+    pub webhook: GitHubWebhook,
+}
+
 #[async_trait]
-impl<'r> FromData<'r> for GitHubWebhook {
+impl<'r> FromData<'r> for VerifiedWebhook {
     type Error = anyhow::Error;
 
     async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
@@ -56,59 +149,290 @@ impl<'r> FromData<'r> for GitHubWebhook {
             Err(e) => return data::Outcome::Error((Status::BadRequest, e.into())),
         };
 
-        let mut hmac = Hmac::<Sha256>::new_from_slice(GITHUB_WEBHOOK_SECRET.as_bytes())
-            .expect("Failed to create hmac.");
-        hmac.update(payload_str.as_bytes());
-
-        if let Some(signature) = req
-            .headers()
-            .get_one("X-Hub-Signature-256")
-            .and_then(|s| s.parse::<GitHubSignature>().ok())
-        {
-            if let Ok(()) = hmac.verify_slice(&signature.payload) {
-                return match parse_and_map_json(req, &payload_str) {
-                    Ok(value) => data::Outcome::Success(value),
-                    Err(err) => data::Outcome::Error(err),
-                };
-            } else {
-                return data::Outcome::Error((
-                    Status::Forbidden,
-                    anyhow!("Mismatching signature."),
-                ));
-            }
-        } else {
+        let webhook_config = &config::get().webhook;
+        if let Err(err) = verify_signature(
+            req.headers().get_one("X-Hub-Signature-256"),
+            req.headers().get_one("X-Hub-Signature"),
+            payload_str.as_bytes(),
+            &webhook_config.accepted_secrets(),
+            webhook_config.allow_sha1,
+        ) {
+            metrics::signature_failure();
+            return data::Outcome::Error(err);
+        }
+
+        let Some(event) = req.headers().get_one("X-GitHub-Event") else {
             return data::Outcome::Error((
-                Status::Unauthorized,
-                anyhow!("Bad or missing signature."),
+                Status::BadRequest,
+                anyhow!("Missing X-GitHub-Event header."),
             ));
+        };
+
+        match parse_webhook(event, &payload_str) {
+            Ok(webhook) => {
+                if let (Some(installation), Some(client)) =
+                    (webhook.installation(), req.rocket().state::<GitHubClient>())
+                {
+                    client
+                        .register_installation(&webhook.repository().full_name, installation.id)
+                        .await;
+                }
+                data::Outcome::Success(VerifiedWebhook {
+                    event: event.to_owned(),
+                    payload: payload_str,
+                    webhook,
+                })
+            }
+            Err(err) => data::Outcome::Error(err),
         }
     }
 }
 
 fn parse<'r, T: Deserialize<'r>>(payload: &'r str) -> Result<T, (Status, anyhow::Error)> {
-    serde_json::from_str::<T>(&payload).map_err(|x| (Status::UnprocessableEntity, anyhow!(x)))
+    serde_json::from_str::<T>(payload).map_err(|x| (Status::UnprocessableEntity, anyhow!(x)))
 }
 
-fn parse_and_map_json<'r>(
-    request: &'r Request<'_>,
-    payload: &'r str,
-) -> Result<GitHubWebhook, (Status, anyhow::Error)> {
-    match request.headers().get_one("X-GitHub-Event") {
-        Some("issue_comment") => {
+/// Parses a webhook payload according to its `X-GitHub-Event` name.
+pub fn parse_webhook(event: &str, payload: &str) -> Result<GitHubWebhook, (Status, anyhow::Error)> {
+    match event {
+        "issue_comment" => {
             parse::<GitHubIssueCommentEvent>(payload).map(GitHubWebhook::IssueComment)
         }
-        Some("issues") => parse::<GitHubIssuesEvent>(payload).map(GitHubWebhook::Issues),
-        Some("pull_request") => {
-            parse::<GitHubPullRequestEvent>(payload).map(GitHubWebhook::PullRequest)
+        "issues" => parse::<GitHubIssuesEvent>(payload).map(GitHubWebhook::Issues),
+        "pull_request" => parse::<GitHubPullRequestEvent>(payload).map(GitHubWebhook::PullRequest),
+        "pull_request_review" => {
+            parse::<GitHubPullRequestReview>(payload).map(GitHubWebhook::PullRequestReview)
         }
-        Some("check_run") => parse::<GitHubCheckRunEvent>(payload).map(GitHubWebhook::CheckRun),
-        Some(event) => Err((
+        "pull_request_review_comment" => parse::<GitHubPullRequestReviewComment>(payload)
+            .map(GitHubWebhook::PullRequestReviewComment),
+        "check_run" => parse::<GitHubCheckRunEvent>(payload).map(GitHubWebhook::CheckRun),
+        event => Err((
             Status::NotImplemented,
             anyhow!("Event {} is not supported.", event),
         )),
-        None => Err((
-            Status::BadRequest,
-            anyhow!("Missing X-GitHub-Event header."),
-        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_webhook, verify_signature};
+    use crate::config::Secret;
+    use crate::github::data::GitHubWebhook;
+    use hmac::{Hmac, Mac};
+    use rocket::http::Status;
+    use serde::Deserialize;
+    use sha1::Sha1;
+    use sha2::Sha256;
+
+    const PAYLOAD: &[u8] = br#"{"action":"created"}"#;
+
+    fn secret(value: &str) -> Secret {
+        Secret::deserialize(serde_json::Value::String(value.to_owned())).unwrap()
+    }
+
+    fn sha256(secret: &str) -> String {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(PAYLOAD);
+        format!("sha256={}", hex::encode(hmac.finalize().into_bytes()))
+    }
+
+    fn sha1(secret: &str) -> String {
+        let mut hmac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(PAYLOAD);
+        format!("sha1={}", hex::encode(hmac.finalize().into_bytes()))
+    }
+
+    fn status(result: Result<(), (Status, anyhow::Error)>) -> Option<Status> {
+        result.err().map(|(status, _)| status)
+    }
+
+    #[test]
+    fn it_should_accept_sha256_signature() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(
+                Some(&sha256("current")),
+                None,
+                PAYLOAD,
+                &secrets,
+                false
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn it_should_accept_any_rotated_secret() {
+        let secrets = [secret("current"), secret("previous")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        for signed_with in ["current", "previous"] {
+            assert_eq!(
+                status(verify_signature(
+                    Some(&sha256(signed_with)),
+                    None,
+                    PAYLOAD,
+                    &secrets,
+                    false
+                )),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_reject_unknown_secret() {
+        let secrets = [secret("current"), secret("previous")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(
+                Some(&sha256("leaked")),
+                None,
+                PAYLOAD,
+                &secrets,
+                false
+            )),
+            Some(Status::Forbidden)
+        );
+    }
+
+    #[test]
+    fn it_should_reject_sha1_unless_enabled() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(
+                None,
+                Some(&sha1("current")),
+                PAYLOAD,
+                &secrets,
+                false
+            )),
+            Some(Status::Unauthorized)
+        );
+        assert_eq!(
+            status(verify_signature(
+                None,
+                Some(&sha1("current")),
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            None
+        );
+        assert_eq!(
+            status(verify_signature(
+                None,
+                Some(&sha1("leaked")),
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            Some(Status::Forbidden)
+        );
+    }
+
+    #[test]
+    fn it_should_prefer_sha256_over_sha1() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(
+                Some(&sha256("leaked")),
+                Some(&sha1("current")),
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            Some(Status::Forbidden)
+        );
+    }
+
+    #[test]
+    fn it_should_reject_mismatched_prefixes() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(
+                Some(&sha1("current")),
+                None,
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            Some(Status::Unauthorized)
+        );
+        assert_eq!(
+            status(verify_signature(
+                None,
+                Some(&sha256("current")),
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            Some(Status::Unauthorized)
+        );
+        assert_eq!(
+            status(verify_signature(
+                Some(&sha256("current").replace("sha256=", "md5=")),
+                None,
+                PAYLOAD,
+                &secrets,
+                true
+            )),
+            Some(Status::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn it_should_reject_malformed_signatures() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        for header in ["sha256=not-hex", "sha256=abc", "sha256", ""] {
+            assert_eq!(
+                status(verify_signature(
+                    Some(header),
+                    None,
+                    PAYLOAD,
+                    &secrets,
+                    false
+                )),
+                Some(Status::Unauthorized),
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_reject_missing_signature() {
+        let secrets = [secret("current")];
+        let secrets = secrets.iter().collect::<Vec<_>>();
+        assert_eq!(
+            status(verify_signature(None, None, PAYLOAD, &secrets, true)),
+            Some(Status::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn it_should_parse_review_events() {
+        let Ok(GitHubWebhook::PullRequestReview(event)) = parse_webhook(
+            "pull_request_review",
+            include_str!("testdata/pull_request_review.json"),
+        ) else {
+            panic!("Failed to parse pull_request_review.");
+        };
+        assert_eq!(event.review.body.as_deref(), Some("@williambotman /fixup"));
+        assert_eq!(event.pull_request.number, 1500);
+        assert!(!event.pull_request.merged);
+
+        let Ok(GitHubWebhook::PullRequestReviewComment(event)) = parse_webhook(
+            "pull_request_review_comment",
+            include_str!("testdata/pull_request_review_comment.json"),
+        ) else {
+            panic!("Failed to parse pull_request_review_comment.");
+        };
+        assert_eq!(event.comment.id, 1445678901);
+        assert_eq!(event.pull_request.head.r#ref, "new-package");
     }
 }
@@ -1 +1,170 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use rocket::{http::Status, State};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+use crate::{
+    config,
+    github::data::GitHubWebhook,
+    jobs::{Job, JobKey, JobQueue},
+    metrics,
+    store::Store,
+};
+
+use self::{
+    delivery::GitHubDelivery,
+    guard::VerifiedWebhook,
+    registry::{Disposition, HandlerRegistry},
+};
+
+pub mod delivery;
 pub mod guard;
+pub mod registry;
+
+async fn dispatch(
+    registry: &HandlerRegistry,
+    store: &Store,
+    jobs: &JobQueue,
+    delivery: GitHubDelivery,
+    verified: VerifiedWebhook,
+) -> Status {
+    let VerifiedWebhook {
+        event,
+        payload,
+        webhook,
+    } = verified;
+    // The command is recorded once (and if) the webhook is parsed as one.
+    let span = info_span!(
+        "delivery",
+        delivery = %delivery.0,
+        event = %event,
+        repo = %webhook.repository().full_name,
+        pr = webhook.issue_number(),
+        command = field::Empty,
+    );
+    let event_name = event.clone();
+
+    let status = async move {
+        info!("Received webhook.");
+
+        if let (Some(max_age), Some(occurred_at)) = (
+            config::get().webhook.max_delivery_age,
+            webhook.occurred_at(),
+        ) {
+            if Utc::now() - occurred_at > Duration::seconds(max_age as i64) {
+                warn!(%occurred_at, "Rejecting stale delivery.");
+                return Status::Forbidden;
+            }
+        }
+
+        let repo = webhook.repository().full_name.to_string();
+        match store.register_delivery(
+            &delivery.0,
+            &event,
+            &repo,
+            config::get().webhook.delivery_history,
+        ) {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("Ignoring duplicate delivery.");
+                return Status::Ok;
+            }
+            Err(err) => {
+                error!(error = ?err, "Failed to record delivery.");
+                return Status::InternalServerError;
+            }
+        }
+
+        let status = handle(registry, jobs, &delivery, event, payload, webhook).await;
+        if let Err(err) = store.set_delivery_status(&delivery.0, status.code) {
+            error!(error = ?err, "Failed to record status of delivery.");
+        }
+        info!(status = status.code, "Handled delivery.");
+        status
+    }
+    .instrument(span)
+    .await;
+    metrics::webhook_delivery(&event_name, status);
+    status
+}
+
+async fn handle(
+    registry: &HandlerRegistry,
+    jobs: &JobQueue,
+    delivery: &GitHubDelivery,
+    event: String,
+    payload: String,
+    webhook: GitHubWebhook,
+) -> Status {
+    let key = JobKey::of(&webhook);
+    let handler = match registry.get(&webhook.repository().full_name) {
+        Some(handler) => handler,
+        None => {
+            warn!("No handler registered for repo.");
+            return Status::NotFound;
+        }
+    };
+
+    match handler.handle(webhook).await {
+        Disposition::Handled(status) => status,
+        Disposition::Enqueue => {
+            let Some(key) = key else {
+                error!("Can't enqueue job without an issue or pull request.");
+                return Status::UnprocessableEntity;
+            };
+            match jobs.enqueue(Job::new(delivery.0.clone(), key, event, payload)) {
+                Ok(()) => Status::Accepted,
+                Err(err) => {
+                    error!(error = ?err, "Failed to enqueue job");
+                    Status::InternalServerError
+                }
+            }
+        }
+        Disposition::Cancel => {
+            if let Some(key) = key {
+                info!(cancelled = jobs.cancel(&key), "Cancelled jobs of {}.", key);
+            }
+            Status::Ok
+        }
+    }
+}
+
+#[post("/v1/github-webhook", format = "json", data = "<webhook>")]
+pub async fn index(
+    registry: &State<HandlerRegistry>,
+    store: &State<Arc<Store>>,
+    jobs: &State<JobQueue>,
+    delivery: GitHubDelivery,
+    webhook: VerifiedWebhook,
+) -> Status {
+    dispatch(registry, store, jobs, delivery, webhook).await
+}
+
+/// Legacy per-repo endpoint, kept as an alias of [`index`].
+#[post("/v1/mason/github-webhook", format = "json", data = "<webhook>")]
+pub async fn mason_index(
+    registry: &State<HandlerRegistry>,
+    store: &State<Arc<Store>>,
+    jobs: &State<JobQueue>,
+    delivery: GitHubDelivery,
+    webhook: VerifiedWebhook,
+) -> Status {
+    dispatch(registry, store, jobs, delivery, webhook).await
+}
+
+/// Legacy per-repo endpoint, kept as an alias of [`index`].
+#[post(
+    "/v1/mason-registry/github-webhook",
+    format = "json",
+    data = "<webhook>"
+)]
+pub async fn mason_registry_index(
+    registry: &State<HandlerRegistry>,
+    store: &State<Arc<Store>>,
+    jobs: &State<JobQueue>,
+    delivery: GitHubDelivery,
+    webhook: VerifiedWebhook,
+) -> Status {
+    dispatch(registry, store, jobs, delivery, webhook).await
+}
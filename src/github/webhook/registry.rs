@@ -0,0 +1,71 @@
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use rocket::http::Status;
+
+use crate::{
+    config::{Config, HandlerKind},
+    generic::GenericHandler,
+    github::{
+        client::GitHubClient,
+        data::{GitHubRepoId, GitHubWebhook},
+    },
+    jobs::JobContext,
+    mason::MasonHandler,
+    mason_registry::MasonRegistryHandler,
+};
+
+/// What became of a webhook once its handler has seen it.
+pub enum Disposition {
+    /// The webhook has been handled inline.
+    Handled(Status),
+    /// The webhook requests a command, which is to be run as a job through
+    /// [`WebhookHandler::run`].
+    Enqueue,
+    /// The webhook requests that the running and queued jobs of its issue or pull request be
+    /// cancelled.
+    Cancel,
+}
+
+/// A set of behaviours (commands, triage, …) that a repo's webhooks are dispatched to.
+#[async_trait]
+pub trait WebhookHandler: Send + Sync {
+    async fn handle(&self, webhook: GitHubWebhook) -> Disposition;
+
+    /// Runs the command of a webhook that was previously enqueued by [`WebhookHandler::handle`].
+    async fn run(
+        &self,
+        webhook: GitHubWebhook,
+        job: JobContext,
+    ) -> anyhow::Result<Box<dyn Display + Send>>;
+}
+
+/// Maps repos to their configured [`WebhookHandler`].
+#[derive(Clone)]
+pub struct HandlerRegistry {
+    handlers: HashMap<String, Arc<dyn WebhookHandler>>,
+}
+
+impl HandlerRegistry {
+    pub fn from_config(config: &Config, client: &GitHubClient) -> Self {
+        let mut handlers = HashMap::new();
+        for (repo, repo_config) in &config.repos {
+            handlers.insert(
+                repo.to_owned(),
+                handler_for(&repo_config.handler, client.clone()),
+            );
+        }
+        Self { handlers }
+    }
+
+    pub fn get(&self, repo: &GitHubRepoId) -> Option<Arc<dyn WebhookHandler>> {
+        self.handlers.get(&repo.to_string()).cloned()
+    }
+}
+
+fn handler_for(kind: &HandlerKind, client: GitHubClient) -> Arc<dyn WebhookHandler> {
+    match kind {
+        HandlerKind::Mason => Arc::new(MasonHandler { client }),
+        HandlerKind::MasonRegistry => Arc::new(MasonRegistryHandler { client }),
+        HandlerKind::Generic => Arc::new(GenericHandler { client }),
+    }
+}
@@ -1,30 +1,32 @@
-use chrono::{Datelike, NaiveDate, Utc};
+use chrono::{Datelike, Utc};
 
-use crate::github::{client, data::GitHubPullRequestEvent};
+use crate::{
+    config,
+    github::{client::GitHubClient, data::GitHubPullRequestEvent},
+};
 
-pub async fn hacktoberfest_label(event: &GitHubPullRequestEvent) {
-    if !event.pull_request.merged {
+pub async fn hacktoberfest_label(client: &GitHubClient, event: &GitHubPullRequestEvent) {
+    let config = config::get();
+    let settings = &config.hacktoberfest;
+    if !settings.enabled || !event.pull_request.merged {
         return;
     }
 
-    match event.pull_request.user.login.as_str() {
-        "williambotman" | "renovate[bot]" => {
-            return;
-        }
-        _ => {}
+    let author = &event.pull_request.user.login;
+    if *author == config.github.login || settings.excluded_authors.contains(author) {
+        return;
     }
 
     let now = Utc::now().date_naive();
-    let start = NaiveDate::from_ymd_opt(now.year(), 9, 25);
-    let end = NaiveDate::from_ymd_opt(now.year(), 11, 5);
-    if let (Some(start), Some(end)) = (start, end) {
+    if let Ok((start, end)) = settings.window(now.year()) {
         if (start <= now) && (now <= end) {
-            let _ = client::add_labels_to_issue(
-                &event.repository,
-                vec!["hacktoberfest-accepted"],
-                event.pull_request.number,
-            )
-            .await;
+            let _ = client
+                .add_labels_to_issue(
+                    &event.repository,
+                    vec!["hacktoberfest-accepted"],
+                    event.pull_request.number,
+                )
+                .await;
         }
     }
 }
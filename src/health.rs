@@ -0,0 +1,244 @@
+use std::{
+    cmp::Ordering,
+    io::Write,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+use tokio::{process::Command, sync::Mutex};
+
+use crate::{config, github::client::GitHubClient};
+
+/// How long the outcome of authenticating against GitHub is reused, so that frequent probes don't
+/// eat into the rate limit.
+const GITHUB_CHECK_TTL: Duration = Duration::from_secs(60);
+const TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref GITHUB_CHECK: Mutex<Option<(Instant, Result<String, String>)>> = Mutex::new(None);
+}
+
+/// A dotted version number, e.g. `0.10.1`. Missing components compare as `0`.
+#[derive(Debug, Clone)]
+pub struct Version(Vec<u64>);
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Version)
+            .map_err(|_| anyhow!("{} is not a valid version.", s))
+    }
+}
+
+impl Version {
+    /// Finds the first version number in the output of e.g. `git --version`.
+    fn find(output: &str) -> Option<Self> {
+        output
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .map(|word| word.trim_matches('.'))
+            .find(|word| !word.is_empty())
+            .and_then(|word| word.parse().ok())
+    }
+
+    fn component(&self, index: usize) -> u64 {
+        self.0.get(index).copied().unwrap_or_default()
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (0..self.0.len().max(other.0.len()))
+            .map(|index| self.component(index).cmp(&other.component(index)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts = self.0.iter().map(u64::to_string).collect::<Vec<_>>();
+        f.write_str(&parts.join("."))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(err) => (false, err.to_string()),
+        };
+        Self {
+            name: name.into(),
+            ok,
+            detail,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Checks that `tool` is on `PATH` and reports at least the `minimum` version.
+async fn check_tool(tool: &str, minimum: &Version) -> Result<String> {
+    let output = tokio::time::timeout(
+        TOOL_TIMEOUT,
+        Command::new(tool)
+            .arg("--version")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| anyhow!("{} --version timed out.", tool))?
+    .map_err(|err| anyhow!("Failed to run {}: {}", tool, err))?;
+    if !output.status.success() {
+        bail!("{} --version exited with {}.", tool, output.status);
+    }
+    let version = Version::find(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow!("Failed to find the version of {}.", tool))?;
+    if version < *minimum {
+        bail!(
+            "{} {} is older than the required {}.",
+            tool,
+            version,
+            minimum
+        );
+    }
+    Ok(version.to_string())
+}
+
+fn check_temp_dir() -> Result<String> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(b"botman")?;
+    Ok(std::env::temp_dir().display().to_string())
+}
+
+fn check_config() -> Result<String> {
+    config::Config::load()?;
+    Ok("valid".to_owned())
+}
+
+async fn check_github(client: &GitHubClient) -> Result<String> {
+    let mut cached = GITHUB_CHECK.lock().await;
+    let result = match &*cached {
+        Some((checked_at, result)) if checked_at.elapsed() < GITHUB_CHECK_TTL => result.clone(),
+        _ => {
+            let result = client
+                .verify()
+                .await
+                .map(|()| "authenticated".to_owned())
+                .map_err(|err| err.to_string());
+            *cached = Some((Instant::now(), result.clone()));
+            result
+        }
+    };
+    result.map_err(|err| anyhow!(err))
+}
+
+/// Liveness probe, answering as long as the server is up.
+#[get("/health")]
+pub fn health() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe, verifying everything commands depend on at runtime: the configuration, the
+/// external tools, the GitHub credentials and a writable temp directory.
+#[get("/ready")]
+pub async fn ready(client: &State<GitHubClient>) -> (Status, Json<Readiness>) {
+    let mut checks = vec![
+        Check::new("config", check_config()),
+        Check::new("temp_dir", check_temp_dir()),
+        Check::new("github", check_github(client).await),
+    ];
+    for (tool, minimum) in &config::get().readiness.tools {
+        let result = match minimum.parse::<Version>() {
+            Ok(minimum) => check_tool(tool, &minimum).await,
+            Err(err) => Err(err),
+        };
+        checks.push(Check::new(format!("tool:{}", tool), result));
+    }
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(Readiness { ready, checks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_temp_dir, check_tool, Version};
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn it_should_compare_versions() {
+        assert!(version("0.10.1") > version("0.9.5"));
+        assert!(version("2.30.2") >= version("2.0"));
+        assert_eq!(version("v0.20"), version("0.20.0"));
+        assert!(version("4.3") < version("4.10"));
+        assert!("0.10-dev".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn it_should_find_version_in_tool_output() {
+        for (output, expected) in [
+            ("git version 2.30.2\n", "2.30.2"),
+            ("GNU Make 4.3\nBuilt for x86_64-pc-linux-gnu", "4.3"),
+            ("NVIM v0.10.1\nBuild type: Release", "0.10.1"),
+            ("stylua 0.20.0\n", "0.20.0"),
+        ] {
+            assert_eq!(Version::find(output), Some(version(expected)), "{}", output);
+        }
+        assert_eq!(Version::find("no version here"), None);
+    }
+
+    #[tokio::test]
+    async fn it_should_check_tools() {
+        assert!(check_tool("git", &version("1.0")).await.is_ok());
+        assert!(check_tool("git", &version("999.0")).await.is_err());
+        assert!(check_tool("botman-missing-tool", &version("1.0"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn it_should_check_temp_dir() {
+        assert!(check_temp_dir().is_ok());
+    }
+}
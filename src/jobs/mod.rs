@@ -0,0 +1,503 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Semaphore,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, field, info, info_span, Instrument};
+
+use crate::{
+    github::{
+        data::GitHubWebhook,
+        webhook::{guard::parse_webhook, registry::HandlerRegistry},
+    },
+    store::{JobStatus, Store},
+};
+
+tokio::task_local! {
+    static CURRENT_JOB: String;
+}
+
+/// The ID of the job being run by the current task, if any.
+pub fn current_job_id() -> Option<String> {
+    CURRENT_JOB.try_with(Clone::clone).ok()
+}
+
+/// The issue or pull request a job operates on. Jobs with the same key are run one at a time, in
+/// the order they were enqueued.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobKey {
+    pub repo: String,
+    pub number: u64,
+}
+
+impl JobKey {
+    pub fn of(webhook: &GitHubWebhook) -> Option<Self> {
+        Some(Self {
+            repo: webhook.repository().full_name.to_string(),
+            number: webhook.issue_number()?,
+        })
+    }
+}
+
+impl Display for JobKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.repo, self.number)
+    }
+}
+
+/// A webhook whose command is yet to be run. The raw payload is kept so that the job can be
+/// persisted and re-parsed after a restart.
+#[derive(Debug)]
+pub struct Job {
+    /// The delivery ID of the webhook that enqueued the job.
+    pub id: String,
+    pub key: JobKey,
+    pub event: String,
+    pub payload: String,
+    pub enqueued_at: DateTime<Utc>,
+}
+
+impl Job {
+    pub fn new(id: String, key: JobKey, event: String, payload: String) -> Self {
+        Self {
+            id,
+            key,
+            event,
+            payload,
+            enqueued_at: Utc::now(),
+        }
+    }
+}
+
+/// Handed to a running job, for it to record its audit trail and to notice cancellation.
+#[derive(Clone)]
+pub struct JobContext {
+    pub id: String,
+    pub cancellation: CancellationToken,
+    pub store: Arc<Store>,
+}
+
+impl std::fmt::Debug for JobContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobContext").field("id", &self.id).finish()
+    }
+}
+
+/// How long a cancellation keeps dropping jobs that were enqueued before it, but had not reached
+/// their lane yet.
+const CANCELLATION_GRACE_MINUTES: i64 = 1;
+
+/// The jobs of a single [`JobKey`].
+#[derive(Default)]
+struct Lane {
+    queued: VecDeque<Job>,
+    running: Option<CancellationToken>,
+    /// Whether a task is running the lane's jobs.
+    active: bool,
+    /// Jobs enqueued until then are dropped when they arrive.
+    cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl Lane {
+    fn is_cancelled(&self, job: &Job) -> bool {
+        self.cancelled_at
+            .is_some_and(|cancelled_at| job.enqueued_at <= cancelled_at)
+    }
+}
+
+type Lanes = Arc<Mutex<HashMap<JobKey, Lane>>>;
+
+/// Queue of jobs, run in the background by a bounded pool of workers. Jobs are persisted in the
+/// [`Store`] until they have run, so that jobs still pending when the process exits are picked up
+/// again on the next start.
+pub struct JobQueue {
+    store: Arc<Store>,
+    sender: UnboundedSender<Job>,
+    receiver: Mutex<Option<UnboundedReceiver<Job>>>,
+    lanes: Lanes,
+}
+
+impl JobQueue {
+    pub fn open(store: Arc<Store>) -> Result<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let pending = store.pending_jobs()?;
+        if !pending.is_empty() {
+            info!(count = pending.len(), "Resuming pending jobs.");
+        }
+        for job in pending {
+            sender.send(job)?;
+        }
+
+        Ok(Self {
+            store,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            lanes: Arc::default(),
+        })
+    }
+
+    pub fn enqueue(&self, job: Job) -> Result<()> {
+        self.store.insert_job(&job)?;
+        info!(job = %job.id, "Enqueued job for {}.", job.key);
+        self.sender
+            .send(job)
+            .map_err(|_| anyhow!("Job queue has been shut down."))
+    }
+
+    /// Cancels the running job of `key` and drops the ones queued after it, including those still
+    /// on their way to the lane. Returns the number of jobs cancelled, not counting the latter.
+    pub fn cancel(&self, key: &JobKey) -> usize {
+        let now = Utc::now();
+        let mut lanes = self.lanes.lock().unwrap();
+        lanes.retain(|_, lane| {
+            lane.active
+                || lane.cancelled_at.is_some_and(|cancelled_at| {
+                    now - cancelled_at < Duration::minutes(CANCELLATION_GRACE_MINUTES)
+                })
+        });
+        let lane = lanes.entry(key.clone()).or_default();
+        lane.cancelled_at = Some(now);
+        let mut cancelled = 0;
+        if let Some(running) = &lane.running {
+            running.cancel();
+            cancelled += 1;
+        }
+        for job in lane.queued.drain(..) {
+            drop_job(&self.store, &job);
+            cancelled += 1;
+        }
+        cancelled
+    }
+
+    /// Starts running queued jobs with at most `workers` of them in flight at once.
+    pub fn start(&self, registry: HandlerRegistry, workers: usize) {
+        self.spawn_workers(workers, move |job, context| {
+            let registry = registry.clone();
+            async move { run(&registry, &job, context).await }
+        });
+    }
+
+    fn spawn_workers<F, Fut>(&self, workers: usize, runner: F)
+    where
+        F: Fn(Job, JobContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        let mut receiver = match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let store = self.store.clone();
+        let lanes = self.lanes.clone();
+        let semaphore = Arc::new(Semaphore::new(workers));
+        let runner = Arc::new(runner);
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                let key = job.key.clone();
+                let mut lanes_guard = lanes.lock().unwrap();
+                let lane = lanes_guard.entry(key.clone()).or_default();
+                if lane.is_cancelled(&job) {
+                    drop_job(&store, &job);
+                    continue;
+                }
+                lane.queued.push_back(job);
+                let is_idle = !std::mem::replace(&mut lane.active, true);
+                drop(lanes_guard);
+                if is_idle {
+                    tokio::spawn(run_lane(
+                        key,
+                        lanes.clone(),
+                        store.clone(),
+                        semaphore.clone(),
+                        runner.clone(),
+                    ));
+                }
+            }
+        });
+    }
+}
+
+/// Runs the jobs of `key` one after another until its lane is empty.
+async fn run_lane<F, Fut>(
+    key: JobKey,
+    lanes: Lanes,
+    store: Arc<Store>,
+    semaphore: Arc<Semaphore>,
+    runner: Arc<F>,
+) where
+    F: Fn(Job, JobContext) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    loop {
+        let (job, cancellation) = {
+            let mut lanes = lanes.lock().unwrap();
+            let lane = lanes.entry(key.clone()).or_default();
+            match lane.queued.pop_front() {
+                Some(job) => {
+                    let cancellation = CancellationToken::new();
+                    lane.running = Some(cancellation.clone());
+                    (job, cancellation)
+                }
+                None if lane.cancelled_at.is_some() => {
+                    // Kept for the jobs that may still arrive from before the cancellation.
+                    lane.running = None;
+                    lane.active = false;
+                    return;
+                }
+                None => {
+                    lanes.remove(&key);
+                    return;
+                }
+            }
+        };
+        let id = job.id.clone();
+        let span = info_span!(
+            "job",
+            delivery = %id,
+            repo = %key.repo,
+            pr = key.number,
+            command = field::Empty,
+        );
+        let permit = semaphore.acquire().await.expect("Job semaphore closed.");
+        let (status, result) = if cancellation.is_cancelled() {
+            info!(parent: &span, "Job was cancelled before it started.");
+            (JobStatus::Cancelled, "Cancelled.".to_owned())
+        } else {
+            if let Err(err) = store.start_job(&id) {
+                error!(parent: &span, error = ?err, "Failed to record start of job.");
+            }
+            let context = JobContext {
+                id: id.clone(),
+                cancellation: cancellation.clone(),
+                store: store.clone(),
+            };
+            match CURRENT_JOB
+                .scope(id.clone(), runner(job, context).instrument(span.clone()))
+                .await
+            {
+                Ok(result) => (JobStatus::Succeeded, result),
+                Err(err) if cancellation.is_cancelled() => {
+                    (JobStatus::Cancelled, format!("{:?}", err))
+                }
+                Err(err) => (JobStatus::Failed, format!("{:?}", err)),
+            }
+        };
+        drop(permit);
+        if let Err(err) = store.finish_job(&id, status, &result) {
+            error!(parent: &span, error = ?err, "Failed to record completion of job.");
+        }
+    }
+}
+
+fn drop_job(store: &Store, job: &Job) {
+    info!(job = %job.id, "Dropping job for {}.", job.key);
+    if let Err(err) = store.finish_job(&job.id, JobStatus::Cancelled, "Cancelled.") {
+        error!(job = %job.id, error = ?err, "Failed to record cancellation of job.");
+    }
+}
+
+async fn run(registry: &HandlerRegistry, job: &Job, context: JobContext) -> Result<String> {
+    let webhook = parse_webhook(&job.event, &job.payload).map_err(|(_, err)| err)?;
+    let handler = registry
+        .get(&webhook.repository().full_name)
+        .ok_or_else(|| {
+            anyhow!(
+                "No handler registered for {}.",
+                webhook.repository().full_name
+            )
+        })?;
+    info!("Running job.");
+    match handler.run(webhook, context).await {
+        Ok(result) => {
+            info!(%result, "Job succeeded.");
+            Ok(result.to_string())
+        }
+        Err(err) => {
+            error!(error = ?err, "Job failed.");
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration as StdDuration,
+    };
+
+    use chrono::Duration;
+    use tempfile::TempDir;
+    use tokio::sync::{Barrier, Notify};
+
+    use super::{Job, JobKey, JobQueue};
+    use crate::store::{JobStatus, Store};
+
+    fn key(number: u64) -> JobKey {
+        JobKey {
+            repo: "williamboman/mason.nvim".to_owned(),
+            number,
+        }
+    }
+
+    fn store() -> (TempDir, Arc<Store>) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::open(&dir.path().join("botman.db")).unwrap());
+        (dir, store)
+    }
+
+    fn enqueue(queue: &JobQueue, id: &str, number: u64, age_minutes: i64) {
+        queue
+            .store
+            .register_delivery(id, "issue_comment", "williamboman/mason.nvim", 100)
+            .unwrap();
+        let mut job = Job::new(
+            id.to_owned(),
+            key(number),
+            "issue_comment".to_owned(),
+            "{}".to_owned(),
+        );
+        job.enqueued_at -= Duration::minutes(age_minutes);
+        queue.enqueue(job).unwrap();
+    }
+
+    fn pending(queue: &JobQueue) -> Vec<String> {
+        let mut receiver = queue.receiver.lock().unwrap().take().unwrap();
+        let mut ids = vec![];
+        while let Ok(job) = receiver.try_recv() {
+            ids.push(job.id);
+        }
+        ids
+    }
+
+    async fn until_drained(store: &Store) {
+        tokio::time::timeout(StdDuration::from_secs(5), async {
+            while !store.pending_jobs().unwrap().is_empty() {
+                tokio::time::sleep(StdDuration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Jobs did not complete.");
+    }
+
+    #[test]
+    fn it_should_resume_pending_jobs_in_order() {
+        let (_dir, store) = store();
+        {
+            let queue = JobQueue::open(store.clone()).unwrap();
+            enqueue(&queue, "b", 1, 1);
+            enqueue(&queue, "c", 1, 0);
+            enqueue(&queue, "a", 1, 2);
+            store.start_job("a").unwrap();
+            store.start_job("c").unwrap();
+            store.finish_job("c", JobStatus::Failed, "Boom.").unwrap();
+        }
+        let queue = JobQueue::open(store).unwrap();
+        assert_eq!(pending(&queue), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn it_should_run_jobs_of_same_pull_request_one_at_a_time() {
+        let (_dir, store) = store();
+        let queue = JobQueue::open(store.clone()).unwrap();
+        let log = Arc::new(Mutex::new(vec![]));
+        let runner_log = log.clone();
+        queue.spawn_workers(4, move |job, _| {
+            let log = runner_log.clone();
+            async move {
+                log.lock().unwrap().push(format!("start {}", job.id));
+                tokio::time::sleep(StdDuration::from_millis(20)).await;
+                log.lock().unwrap().push(format!("end {}", job.id));
+                Ok("Done.".to_owned())
+            }
+        });
+        enqueue(&queue, "a", 1, 0);
+        enqueue(&queue, "b", 1, 0);
+        until_drained(&store).await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["start a", "end a", "start b", "end b"]
+        );
+        assert_eq!(store.job_status("b").unwrap(), Some(JobStatus::Succeeded));
+    }
+
+    #[tokio::test]
+    async fn it_should_run_jobs_of_different_pull_requests_concurrently() {
+        let (_dir, store) = store();
+        let queue = JobQueue::open(store.clone()).unwrap();
+        // Neither job can complete unless the other one runs at the same time.
+        let barrier = Arc::new(Barrier::new(2));
+        queue.spawn_workers(4, move |_, _| {
+            let barrier = barrier.clone();
+            async move {
+                barrier.wait().await;
+                Ok("Done.".to_owned())
+            }
+        });
+        enqueue(&queue, "a", 1, 0);
+        enqueue(&queue, "b", 2, 0);
+        until_drained(&store).await;
+    }
+
+    #[tokio::test]
+    async fn it_should_cancel_running_job_and_drop_queued_ones() {
+        let (_dir, store) = store();
+        let queue = JobQueue::open(store.clone()).unwrap();
+        let log = Arc::new(Mutex::new(vec![]));
+        let started = Arc::new(Notify::new());
+        let (runner_log, runner_started) = (log.clone(), started.clone());
+        queue.spawn_workers(4, move |job, context| {
+            let (log, started) = (runner_log.clone(), runner_started.clone());
+            async move {
+                log.lock().unwrap().push(format!("start {}", job.id));
+                started.notify_one();
+                context.cancellation.cancelled().await;
+                log.lock().unwrap().push(format!("cancelled {}", job.id));
+                anyhow::bail!("Cancelled.")
+            }
+        });
+        enqueue(&queue, "a", 1, 0);
+        enqueue(&queue, "b", 1, 0);
+        enqueue(&queue, "c", 1, 0);
+        started.notified().await;
+        assert_eq!(queue.cancel(&key(2)), 0);
+        assert_eq!(queue.cancel(&key(1)), 3);
+        until_drained(&store).await;
+        assert_eq!(*log.lock().unwrap(), vec!["start a", "cancelled a"]);
+        for id in ["a", "b", "c"] {
+            assert_eq!(store.job_status(id).unwrap(), Some(JobStatus::Cancelled));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_drop_jobs_that_reach_a_cancelled_lane_late() {
+        let (_dir, store) = store();
+        let queue = JobQueue::open(store.clone()).unwrap();
+        // No worker has picked "a" off the channel yet when the pull request is cancelled.
+        enqueue(&queue, "a", 1, 0);
+        assert_eq!(queue.cancel(&key(1)), 0);
+        enqueue(&queue, "b", 1, 0);
+        let log = Arc::new(Mutex::new(vec![]));
+        let runner_log = log.clone();
+        queue.spawn_workers(4, move |job, _| {
+            let log = runner_log.clone();
+            async move {
+                log.lock().unwrap().push(job.id);
+                Ok("Done.".to_owned())
+            }
+        });
+        until_drained(&store).await;
+        assert_eq!(*log.lock().unwrap(), vec!["b"]);
+        assert_eq!(store.job_status("a").unwrap(), Some(JobStatus::Cancelled));
+        assert_eq!(store.job_status("b").unwrap(), Some(JobStatus::Succeeded));
+    }
+}
@@ -0,0 +1,24 @@
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Installs the global `tracing` subscriber. Closing spans are logged as well, so that every
+/// delivery, job and spawned command reports how long it took.
+pub fn init(config: &LogConfig) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.filter));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    let result = match config.format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to install log subscriber: {}", err);
+    }
+}
@@ -1,6 +1,15 @@
-use std::env::var;
+use std::sync::Arc;
 
-use reqwest::Client;
+use github::{
+    action::parser,
+    client::GitHubClient,
+    projects,
+    webhook::{self, registry::HandlerRegistry},
+};
+use jobs::JobQueue;
+use rocket::fairing::AdHoc;
+use store::Store;
+use tracing::{error, warn};
 
 #[macro_use]
 extern crate rocket;
@@ -8,22 +17,87 @@ extern crate rocket;
 #[macro_use]
 extern crate lazy_static;
 
+mod admin;
+mod config;
+mod generic;
 mod github;
 mod hacktober;
+mod health;
+mod jobs;
+mod logging;
 mod mason;
 mod mason_registry;
+mod metrics;
+mod store;
+mod triage;
 mod workspace;
 
-// TODO: verify these exist at startup
-lazy_static! {
-    static ref CLIENT: Client = reqwest::Client::new();
-    static ref GITHUB_LOGIN: String = var("GITHUB_LOGIN").expect("No GITHUB_LOGIN.");
-    static ref GITHUB_PAT: String = var("GITHUB_PAT").expect("No GITHUB_PAT.");
-    static ref GITHUB_WEBHOOK_SECRET: String =
-        var("GITHUB_WEBHOOK_SECRET").expect("No GITHUB_WEBHOOK_SECRET.");
-}
-
 #[launch]
 fn rocket() -> _ {
-    rocket::build().mount("/api", routes![mason::index, mason_registry::index])
+    if let Err(err) = config::Config::load().and_then(config::init) {
+        eprintln!("{:?}", err);
+        std::process::exit(1);
+    }
+    let config = config::get();
+    logging::init(&config.log);
+
+    let store = Store::open(&config.state_dir.join("botman.db")).unwrap_or_else(|err| {
+        error!(error = ?err, "Failed to open state store");
+        std::process::exit(1);
+    });
+    let store = Arc::new(store);
+
+    let jobs = JobQueue::open(store.clone()).unwrap_or_else(|err| {
+        error!(error = ?err, "Failed to open job queue");
+        std::process::exit(1);
+    });
+
+    let mut client = GitHubClient::from_config(&config.github);
+    if config.dry_run {
+        warn!("Running in dry-run mode, GitHub mutations and pushes are only recorded.");
+        client = client.with_dry_run(store.clone());
+    }
+
+    rocket::build()
+        .manage(HandlerRegistry::from_config(config, &client))
+        .manage(client)
+        .manage(store)
+        .manage(jobs)
+        .attach(AdHoc::on_liftoff("Identity", |rocket| {
+            Box::pin(async move {
+                if let Some(client) = rocket.state::<GitHubClient>() {
+                    parser::resolve_identity(client).await;
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Job workers", |rocket| {
+            Box::pin(async move {
+                if let (Some(jobs), Some(registry)) = (
+                    rocket.state::<JobQueue>(),
+                    rocket.state::<HandlerRegistry>(),
+                ) {
+                    jobs.start(registry.clone(), config::get().jobs.workers);
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Projects", |rocket| {
+            Box::pin(async move {
+                if let Some(client) = rocket.state::<GitHubClient>() {
+                    projects::resolve_all(client).await;
+                }
+            })
+        }))
+        .mount(
+            "/api",
+            routes![
+                webhook::index,
+                webhook::mason_index,
+                webhook::mason_registry_index,
+                admin::jobs,
+                admin::job,
+                admin::actions,
+                admin::planned_operations
+            ],
+        )
+        .mount("/", routes![health::health, health::ready, metrics::index])
 }
@@ -1,25 +1,41 @@
-use crate::{github::action::parser::AuthorizedAction, workspace::Workspace};
+use crate::{
+    config::CommandPermission,
+    github::action::spec::{CommandSpec, FlagSpec},
+    metrics,
+    workspace::Workspace,
+};
 use anyhow::Result;
 
-use rocket::http::Status;
-use std::fmt::Display;
+use tracing::info;
 
-use super::MasonCommand;
+pub(super) static FIXUP: CommandSpec = CommandSpec {
+    name: "fixup",
+    aliases: &[],
+    args: &[],
+    flags: &[FlagSpec {
+        name: "no-merge",
+        description: "Don't merge the base branch first.",
+        value: None,
+    }],
+    description: "Merges the base branch, regenerates code and formats it with stylua.",
+    // Runs the pull request's Makefile.
+    permission: CommandPermission::Maintainer,
+};
 
 async fn make_generate(workspace: &Workspace) -> Result<()> {
-    println!("Generating code…");
+    info!("Generating code…");
     let _ = workspace.spawn("make", ["generate"]).await?;
     Ok(())
 }
 
 async fn stylua(workspace: &Workspace) -> Result<()> {
-    println!("Running stylua…");
+    info!("Running stylua…");
     let _ = workspace.spawn("stylua", ["."]).await?;
     Ok(())
 }
 
 async fn restore_generated_code(workspace: &Workspace) -> Result<()> {
-    println!("Restoring generated code…");
+    info!("Restoring generated code…");
     let _ = workspace
         .spawn(
             "git",
@@ -37,25 +53,16 @@ async fn restore_generated_code(workspace: &Workspace) -> Result<()> {
     Ok(())
 }
 
-pub(super) async fn run(
-    action: &AuthorizedAction<MasonCommand>,
-) -> Result<Box<dyn Display + Send>, (Status, anyhow::Error)> {
-    let workspace = Workspace::create(&action).await?;
-
-    async {
+pub(super) async fn run(workspace: &Workspace, merge: bool) -> Result<()> {
+    if merge {
         workspace.merge_with_base().await?;
-        make_generate(&workspace).await?;
-        stylua(&workspace).await?;
-        let _ = restore_generated_code(&workspace).await;
-        let _ = workspace.commit("fixup").await;
-        workspace.push().await?;
-        Ok::<(), anyhow::Error>(())
     }
-    .await
-    .map_err(|err| (Status::InternalServerError, err))?;
-
-    Ok(Box::new(format!(
-        "Successfully ran mason fixup in {:?}",
-        workspace
-    )))
+    metrics::workspace_stage("generate", async {
+        make_generate(workspace).await?;
+        stylua(workspace).await
+    })
+    .await?;
+    let _ = restore_generated_code(workspace).await;
+    let _ = workspace.commit("fixup").await;
+    Ok(())
 }
@@ -1,21 +1,37 @@
 use std::fmt::Display;
 
 use crate::{
+    config,
     github::{
-        action::{common::GitApplyPatch, parser::*},
-        client,
+        action::{
+            self,
+            common::GitApplyPatch,
+            parser::*,
+            spec::{self, CommandSpec},
+        },
+        client::GitHubClient,
         data::{GitHubIssuesEvent, GitHubIssuesEventAction, GitHubPullRequestEvent, GitHubWebhook},
+        projects,
+        webhook::registry::{Disposition, WebhookHandler},
     },
     hacktober::hacktoberfest_label,
+    jobs::JobContext,
+    workspace::Workspace,
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use rocket::http::Status;
 
 mod fixup;
 
+static COMMANDS: &[&CommandSpec] = &[
+    &action::apply::APPLY,
+    &action::merge_base::MERGE_BASE,
+    &fixup::FIXUP,
+];
+
 #[derive(Debug)]
 enum MasonCommand {
-    Fixup,
+    Fixup { merge: bool },
     MergeBase,
     Apply(GitApplyPatch),
 }
@@ -24,85 +40,113 @@ impl TryFrom<RawCommand> for MasonCommand {
     type Error = anyhow::Error;
 
     fn try_from(value: RawCommand) -> Result<Self, Self::Error> {
-        match value.raw_command.as_str() {
-            "fixup" => Ok(Self::Fixup),
-            "apply" => {
-                let arguments = value
-                    .raw_arguments
-                    .ok_or_else(|| anyhow!("apply is missing arguments."))?;
-                Ok(Self::Apply(arguments.try_into()?))
-            }
+        let (spec, args) = spec::parse(MasonCommand::specs(), value)?;
+        match spec.name {
+            "fixup" => Ok(Self::Fixup {
+                merge: !args.flag("no-merge"),
+            }),
             "merge-base" => Ok(Self::MergeBase),
-            s => bail!("{} is not a valid mason command.", s),
+            "apply" => Ok(Self::Apply(
+                args.arg("patch")
+                    .unwrap_or_default()
+                    .to_owned()
+                    .try_into()?,
+            )),
+            name => bail!("/{} is not implemented.", name),
         }
     }
 }
 
 #[async_trait]
 impl AuthorizedActionExecutor for MasonCommand {
-    async fn execute(
-        action: AuthorizedAction<MasonCommand>,
-    ) -> Result<Box<dyn Display + Send>, (Status, anyhow::Error)> {
-        match &action.action.command {
-            MasonCommand::Fixup => fixup::run(&action).await,
-            MasonCommand::Apply(patch) => crate::github::action::apply::run(&action, patch).await,
-            MasonCommand::MergeBase => crate::github::action::merge_base::run(&action).await,
+    fn specs() -> &'static [&'static CommandSpec] {
+        COMMANDS
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        match self {
+            MasonCommand::Fixup { .. } => &fixup::FIXUP,
+            MasonCommand::MergeBase => &action::merge_base::MERGE_BASE,
+            MasonCommand::Apply(_) => &action::apply::APPLY,
+        }
+    }
+
+    async fn execute(&self, workspace: &Workspace) -> Result<()> {
+        match self {
+            MasonCommand::Fixup { merge } => fixup::run(workspace, *merge).await,
+            MasonCommand::MergeBase => action::merge_base::run(workspace).await,
+            MasonCommand::Apply(patch) => action::apply::run(workspace, patch).await,
         }
     }
 }
 
 const NEW_PACKAGE_COMMENT: &str = r#"Hello! Pull requests are always very welcomed to add new packages. If the distribution of the package is simple, the installation will most likely be so as well. See [CONTRIBUTING.md](https://github.com/williamboman/mason.nvim/blob/main/CONTRIBUTING.md) and the [API reference](https://github.com/williamboman/mason.nvim/blob/main/doc/reference.md) for more details! You may also use existing packages as reference."#;
 
-lazy_static! {
-    static ref MASON_PROJECT_ID: u64 = 14574269;
-    static ref MASON_PROJECT_COLUMN_PRIO_ID: u64 = 19009769;
-    static ref MASON_PROJECT_COLUMN_TRIAGE_ID: u64 = 19009768;
-    static ref MASON_PROJECT_COLUMN_BACKLOG_ID: u64 = 19009770;
-    static ref MASON_PROJECT_COLUMN_SUPPORT_ID: u64 = 19114644;
-    static ref MASON_PROJECT_COLUMN_CLOSED_ID: u64 = 19009772;
-}
+async fn issue_event(client: &GitHubClient, event: GitHubIssuesEvent) -> Status {
+    let Some(project) = config::get()
+        .repo(&event.repository.full_name)
+        .and_then(|repo| repo.project.as_ref())
+    else {
+        return Status::NoContent;
+    };
 
-async fn issue_event(event: GitHubIssuesEvent) -> Status {
-    match event.action {
-        GitHubIssuesEventAction::Opened => {
-            if event.issue.has_label("new-package-request") {
-                let _ = tokio::join!(
-                    client::create_issue_comment(
-                        &event.repository,
-                        event.issue.number,
-                        NEW_PACKAGE_COMMENT,
-                    ),
-                    client::create_column_card(*MASON_PROJECT_COLUMN_PRIO_ID, event.issue.id),
-                    client::add_labels_to_issue(
-                        &event.repository,
-                        vec!["help wanted"],
-                        event.issue.number,
-                    )
-                );
-            } else {
-                let _ = client::create_column_card(*MASON_PROJECT_COLUMN_TRIAGE_ID, event.issue.id)
-                    .await;
-            }
-            Status::NoContent
+    let set_status = async {
+        if let Some(status) = projects::status_for_event(project, &event) {
+            let _ = projects::set_status(
+                client,
+                &event.repository.full_name,
+                project,
+                &event.issue.node_id,
+                status,
+            )
+            .await;
         }
-        _ => Status::NoContent,
+    };
+
+    if event.action == GitHubIssuesEventAction::Opened
+        && event.issue.has_label("new-package-request")
+    {
+        let _ = tokio::join!(
+            client
+                .create_issue_comment(&event.repository, event.issue.number, NEW_PACKAGE_COMMENT,),
+            set_status,
+            client.add_labels_to_issue(&event.repository, vec!["help wanted"], event.issue.number,)
+        );
+    } else {
+        set_status.await;
     }
+    Status::NoContent
 }
 
-async fn pull_request(event: GitHubPullRequestEvent) -> Status {
-    hacktoberfest_label(&event).await;
+async fn pull_request(client: &GitHubClient, event: GitHubPullRequestEvent) -> Status {
+    hacktoberfest_label(client, &event).await;
     Status::NoContent
 }
 
-#[post("/v1/mason/github-webhook", format = "json", data = "<webhook>")]
-pub async fn index(webhook: GitHubWebhook) -> Status {
-    println!("{:?}", webhook);
-    match webhook {
-        GitHubWebhook::IssueComment(event) => {
-            crate::github::action::handle_issue_comment::<MasonCommand>(event).await
+pub struct MasonHandler {
+    pub client: GitHubClient,
+}
+
+#[async_trait]
+impl WebhookHandler for MasonHandler {
+    async fn handle(&self, webhook: GitHubWebhook) -> Disposition {
+        match webhook {
+            GitHubWebhook::Issues(event) => {
+                Disposition::Handled(issue_event(&self.client, event).await)
+            }
+            GitHubWebhook::PullRequest(event) => {
+                Disposition::Handled(pull_request(&self.client, event).await)
+            }
+            GitHubWebhook::CheckRun(_) => Disposition::Handled(Status::NotImplemented),
+            webhook => action::accept::<MasonCommand>(&self.client, webhook).await,
         }
-        GitHubWebhook::Issues(event) => issue_event(event).await,
-        GitHubWebhook::PullRequest(event) => pull_request(event).await,
-        _ => Status::NotImplemented,
+    }
+
+    async fn run(
+        &self,
+        webhook: GitHubWebhook,
+        job: JobContext,
+    ) -> Result<Box<dyn Display + Send>> {
+        action::run::<MasonCommand>(&self.client, webhook, job).await
     }
 }
@@ -1,16 +1,32 @@
 use anyhow::Result;
 use async_recursion::async_recursion;
 use lazy_static::__Deref;
-use rocket::http::Status;
-use std::{collections::HashSet, fmt::Display, path::PathBuf};
+use std::{collections::HashSet, path::PathBuf};
 use tokio::{
     fs::{self, DirEntry, File},
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
 };
 
-use crate::{github::action::parser::AuthorizedAction, workspace::Workspace};
+use crate::{
+    config::CommandPermission,
+    github::action::spec::{CommandSpec, FlagSpec},
+    metrics,
+    workspace::Workspace,
+};
 
-use super::MasonRegistryCommand;
+pub(super) static FIXUP: CommandSpec = CommandSpec {
+    name: "fixup",
+    aliases: &[],
+    args: &[],
+    flags: &[FlagSpec {
+        name: "no-merge",
+        description: "Don't merge the base branch first.",
+        value: None,
+    }],
+    description: "Merges the base branch, renames changed `.yml` package files to `.yaml` and \
+        fixes their formatting.",
+    permission: CommandPermission::Maintainer,
+};
 
 #[async_recursion]
 async fn read_dir_recursively(dir: &PathBuf, entries: &mut Vec<DirEntry>) -> Result<()> {
@@ -74,13 +90,13 @@ async fn yml_to_yaml(workspace: &Workspace, changed_files: &HashSet<PathBuf>) ->
     Ok(())
 }
 
-fn apply_styling_fixes<'a>(lines: &'a Vec<String>) -> Vec<&'a str> {
+fn apply_styling_fixes(lines: &[String]) -> Vec<&str> {
     let mut new_file_lines = vec![];
     let mut it = lines.windows(2).peekable();
     while let Some(slice) = it.next() {
-        if let (Some(line1), Some(line2)) = (slice.get(0), slice.get(1)) {
+        if let (Some(line1), Some(line2)) = (slice.first(), slice.get(1)) {
             match (line1.as_str(), line2.as_str()) {
-                (line1, _line2 @ ("source:" | "bin:" | "share:" | "opt:")) if line1 != "" => {
+                (line1, _line2 @ ("source:" | "bin:" | "share:" | "opt:")) if !line1.is_empty() => {
                     new_file_lines.push(line1);
                     new_file_lines.push("");
                 }
@@ -95,10 +111,10 @@ fn apply_styling_fixes<'a>(lines: &'a Vec<String>) -> Vec<&'a str> {
             new_file_lines.extend(slice.iter().map(|s| s.as_str()));
         }
     }
-    if new_file_lines.get(0) != Some(&"---") {
+    if new_file_lines.first() != Some(&"---") {
         new_file_lines.insert(0, "---");
     }
-    return new_file_lines;
+    new_file_lines
 }
 
 async fn fix_styling(workspace: &Workspace, changed_files: &HashSet<PathBuf>) -> Result<()> {
@@ -148,41 +164,35 @@ async fn fix_styling(workspace: &Workspace, changed_files: &HashSet<PathBuf>) ->
     Ok(())
 }
 
-pub(super) async fn run(
-    action: &AuthorizedAction<MasonRegistryCommand>,
-) -> Result<Box<dyn Display + Send>, (Status, anyhow::Error)> {
-    let workspace = Workspace::create(&action).await?;
-
-    async {
+pub(super) async fn run(workspace: &Workspace, merge: bool) -> Result<()> {
+    if merge {
         workspace.merge_with_base().await?;
-        let changed_files = workspace
-            .get_changed_files()
-            .await?
-            .iter()
-            .map(|path| {
-                let mut new_path = PathBuf::new();
-                new_path.push(workspace.workdir.path());
-                new_path.push(path);
-                new_path
-            })
-            .collect::<HashSet<PathBuf>>();
-        yml_to_yaml(&workspace, &changed_files).await?;
-        fix_styling(&workspace, &changed_files).await?;
-        workspace.push().await?;
-        Ok::<(), anyhow::Error>(())
     }
+    let changed_files = workspace
+        .get_changed_files()
+        .await?
+        .iter()
+        .map(|path| {
+            let mut new_path = PathBuf::new();
+            new_path.push(workspace.workdir.path());
+            new_path.push(path);
+            new_path
+        })
+        .collect::<HashSet<PathBuf>>();
+    metrics::workspace_stage("generate", async {
+        yml_to_yaml(workspace, &changed_files).await?;
+        fix_styling(workspace, &changed_files).await
+    })
     .await
-    .map_err(|err| (Status::InternalServerError, err))?;
-
-    Ok(Box::new(format!(
-        "Successfully ran mason-registry fixup in {:?}",
-        workspace
-    )))
 }
 
 #[cfg(test)]
 mod tests {
     use super::apply_styling_fixes;
+    use crate::{
+        config::CommandPermission, github::action::parser::AuthorizedActionExecutor,
+        mason_registry::MasonRegistryCommand,
+    };
     use indoc::indoc;
 
     fn from_str(str: &str) -> Vec<String> {
@@ -258,4 +268,17 @@ mod tests {
         "#})))
         );
     }
+
+    #[test]
+    fn it_should_reserve_commands_to_maintainers_by_default() {
+        // The commands run the pull request's code in a workspace holding the bot's credentials.
+        for spec in MasonRegistryCommand::specs() {
+            assert_eq!(
+                spec.permission,
+                CommandPermission::Maintainer,
+                "/{} is open to non-maintainers",
+                spec.name
+            );
+        }
+    }
 }
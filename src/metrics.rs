@@ -0,0 +1,222 @@
+use std::{future::Future, time::Instant};
+
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use reqwest::Response;
+use rocket::http::{ContentType, Status};
+use tracing::error;
+
+lazy_static! {
+    static ref WEBHOOK_DELIVERIES: IntCounterVec = register_int_counter_vec!(
+        "botman_webhook_deliveries_total",
+        "Webhook deliveries by event and response status.",
+        &["event", "status"]
+    )
+    .unwrap();
+    static ref SIGNATURE_FAILURES: IntCounter = register_int_counter!(
+        "botman_webhook_signature_failures_total",
+        "Webhook deliveries rejected because of a bad or missing signature."
+    )
+    .unwrap();
+    static ref COMMANDS: IntCounterVec = register_int_counter_vec!(
+        "botman_commands_total",
+        "Commands executed by name and result.",
+        &["command", "result"]
+    )
+    .unwrap();
+    static ref WORKSPACE_STAGE_DURATION: HistogramVec = register_histogram_vec!(
+        "botman_workspace_stage_duration_seconds",
+        "Time spent in each stage of a workspace (clone, merge, generate, push).",
+        &["stage"],
+        vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+    )
+    .unwrap();
+    static ref GITHUB_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "botman_github_api_requests_total",
+        "GitHub API requests by endpoint and response status.",
+        &["endpoint", "status"]
+    )
+    .unwrap();
+    static ref GITHUB_RATE_LIMIT_REMAINING: IntGaugeVec = register_int_gauge_vec!(
+        "botman_github_rate_limit_remaining",
+        "Requests remaining in the current GitHub rate limit window, by resource.",
+        &["resource"]
+    )
+    .unwrap();
+}
+
+pub fn webhook_delivery(event: &str, status: Status) {
+    WEBHOOK_DELIVERIES
+        .with_label_values(&[event, status.code.to_string().as_str()])
+        .inc();
+}
+
+pub fn signature_failure() {
+    SIGNATURE_FAILURES.inc();
+}
+
+/// The result of an executed command, as reported in `botman_commands_total`.
+#[derive(Debug, Clone, Copy)]
+pub enum CommandResult {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl CommandResult {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+pub fn command(name: &str, result: CommandResult) {
+    COMMANDS.with_label_values(&[name, result.as_str()]).inc();
+}
+
+/// Runs one stage of a workspace, recording how long it took whether or not it succeeded.
+pub async fn workspace_stage<T>(stage: &str, future: impl Future<Output = T>) -> T {
+    let started_at = Instant::now();
+    let output = future.await;
+    WORKSPACE_STAGE_DURATION
+        .with_label_values(&[stage])
+        .observe(started_at.elapsed().as_secs_f64());
+    output
+}
+
+/// Records a GitHub API response along with the rate limit it reports.
+pub fn github_response(url: &str, response: &Response) {
+    GITHUB_REQUESTS
+        .with_label_values(&[github_endpoint(url).as_str(), response.status().as_str()])
+        .inc();
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    if let Some(remaining) = header("x-ratelimit-remaining").and_then(|value| value.parse().ok()) {
+        GITHUB_RATE_LIMIT_REMAINING
+            .with_label_values(&[header("x-ratelimit-resource").unwrap_or("core")])
+            .set(remaining);
+    }
+}
+
+/// Records a GitHub API request that failed without a response.
+pub fn github_request_error(url: &str) {
+    GITHUB_REQUESTS
+        .with_label_values(&[github_endpoint(url).as_str(), "error"])
+        .inc();
+}
+
+/// Reduces a GitHub API url to its route, e.g. `/repos/:owner/:repo/issues/:id/comments`, so that
+/// the number of label values stays bounded.
+fn github_endpoint(url: &str) -> String {
+    let path = url.split_once("://").map_or(url, |(_, rest)| {
+        rest.find('/').map_or("", |start| &rest[start..])
+    });
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let mut endpoint = String::new();
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    while let Some(segment) = segments.next() {
+        endpoint.push('/');
+        if segment.chars().all(|c| c.is_ascii_digit()) {
+            endpoint.push_str(":id");
+        } else {
+            endpoint.push_str(segment);
+        }
+        let placeholders: &[&str] = match segment {
+            "repos" => &[":owner", ":repo"],
+            "orgs" => &[":org"],
+            "teams" => &[":team"],
+            "commits" => &[":ref"],
+            "collaborators" | "memberships" => &[":user"],
+            _ => &[],
+        };
+        for placeholder in placeholders {
+            if segments.next().is_some() {
+                endpoint.push('/');
+                endpoint.push_str(placeholder);
+            }
+        }
+    }
+    endpoint
+}
+
+#[get("/metrics")]
+pub fn index() -> Result<(ContentType, String), Status> {
+    // Metrics are registered on first use, register all of them so that they're exported from the start.
+    lazy_static::initialize(&WEBHOOK_DELIVERIES);
+    lazy_static::initialize(&SIGNATURE_FAILURES);
+    lazy_static::initialize(&COMMANDS);
+    lazy_static::initialize(&WORKSPACE_STAGE_DURATION);
+    lazy_static::initialize(&GITHUB_REQUESTS);
+    lazy_static::initialize(&GITHUB_RATE_LIMIT_REMAINING);
+
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|err| {
+            error!(error = ?err, "Failed to encode metrics.");
+            Status::InternalServerError
+        })?;
+    let body = String::from_utf8(buffer).map_err(|_| Status::InternalServerError)?;
+    Ok((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::github_endpoint;
+
+    #[test]
+    fn it_should_template_github_endpoints() {
+        assert_eq!(
+            github_endpoint(
+                "https://api.github.com/repos/williamboman/mason.nvim/issues/12/comments"
+            ),
+            "/repos/:owner/:repo/issues/:id/comments"
+        );
+        assert_eq!(
+            github_endpoint("https://api.github.com/repos/williamboman/mason.nvim/pulls/3?page=2"),
+            "/repos/:owner/:repo/pulls/:id"
+        );
+        assert_eq!(
+            github_endpoint("https://api.github.com/app/installations/42/access_tokens"),
+            "/app/installations/:id/access_tokens"
+        );
+        assert_eq!(
+            github_endpoint("http://127.0.0.1:8080/repos/octo/cat/installation"),
+            "/repos/:owner/:repo/installation"
+        );
+        assert_eq!(
+            github_endpoint(
+                "https://api.github.com/repos/williamboman/mason.nvim/commits/3f2a9c1/check-runs?per_page=100"
+            ),
+            "/repos/:owner/:repo/commits/:ref/check-runs"
+        );
+        assert_eq!(
+            github_endpoint("https://api.github.com/orgs/mason-org/teams/core/members"),
+            "/orgs/:org/teams/:team/members"
+        );
+        assert_eq!(
+            github_endpoint(
+                "https://api.github.com/repos/williamboman/mason.nvim/collaborators/octocat/permission"
+            ),
+            "/repos/:owner/:repo/collaborators/:user/permission"
+        );
+        assert_eq!(
+            github_endpoint("https://api.github.com/graphql"),
+            "/graphql"
+        );
+    }
+}
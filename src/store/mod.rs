@@ -0,0 +1,921 @@
+use std::{fs, path::Path, str::FromStr, sync::Mutex};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use tracing::info;
+
+use crate::jobs::{Job, JobKey};
+
+/// Schema migrations, applied in order. The index of the last applied migration (plus one) is
+/// tracked in SQLite's `user_version`, so migrations must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE deliveries (
+        id TEXT PRIMARY KEY,
+        event TEXT NOT NULL,
+        repo TEXT NOT NULL,
+        received_at TEXT NOT NULL,
+        status INTEGER
+    );
+
+    CREATE TABLE jobs (
+        id TEXT PRIMARY KEY REFERENCES deliveries (id),
+        repo TEXT NOT NULL,
+        number INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        result TEXT,
+        enqueued_at TEXT NOT NULL,
+        started_at TEXT,
+        finished_at TEXT
+    );
+    CREATE INDEX jobs_status ON jobs (status, enqueued_at);
+
+    CREATE TABLE actions (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT NOT NULL REFERENCES jobs (id),
+        command TEXT NOT NULL,
+        authorized_by TEXT NOT NULL,
+        repo TEXT NOT NULL,
+        pull_request INTEGER,
+        head_sha TEXT,
+        recorded_at TEXT NOT NULL
+    );
+
+    CREATE TABLE command_outputs (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT NOT NULL REFERENCES jobs (id),
+        command TEXT NOT NULL,
+        exit_code INTEGER,
+        stdout TEXT NOT NULL,
+        stderr TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE actions ADD COLUMN details TEXT;
+    CREATE INDEX actions_job_id ON actions (job_id);
+    CREATE INDEX command_outputs_job_id ON command_outputs (job_id);
+"#,
+    r#"
+    CREATE TABLE planned_operations (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT REFERENCES jobs (id),
+        repo TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        details TEXT NOT NULL,
+        recorded_at TEXT NOT NULL
+    );
+    CREATE INDEX planned_operations_job_id ON planned_operations (job_id);
+"#,
+    r#"
+    CREATE INDEX deliveries_received_at ON deliveries (received_at);
+"#,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for JobStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(JobStatus::Queued),
+            "running" => Ok(JobStatus::Running),
+            "succeeded" => Ok(JobStatus::Succeeded),
+            "failed" => Ok(JobStatus::Failed),
+            "cancelled" => Ok(JobStatus::Cancelled),
+            s => bail!("{} is not a valid job status.", s),
+        }
+    }
+}
+
+/// An action parsed from a comment, as recorded for auditing.
+pub struct ActionRecord<'a> {
+    pub job_id: &'a str,
+    pub command: String,
+    pub details: String,
+    pub authorized_by: &'a str,
+    pub repo: String,
+    pub pull_request: Option<u64>,
+    pub head_sha: Option<&'a str>,
+}
+
+/// The output of a process spawned in a workspace.
+pub struct CommandOutputRecord<'a> {
+    pub job_id: &'a str,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub stdout: &'a [u8],
+    pub stderr: &'a [u8],
+    pub started_at: DateTime<Utc>,
+}
+
+/// A GitHub mutation or push that was skipped in dry-run mode.
+pub struct PlannedOperationRecord<'a> {
+    /// The job the operation was planned by, unless it was planned while handling a webhook inline.
+    pub job_id: Option<&'a str>,
+    pub repo: String,
+    pub operation: &'a str,
+    pub details: String,
+}
+
+/// Criteria to list jobs by. Unset criteria match every job.
+#[derive(Debug, Default)]
+pub struct JobFilter<'a> {
+    pub repo: Option<&'a str>,
+    pub pull_request: Option<u64>,
+    pub command: Option<&'a str>,
+    pub status: Option<JobStatus>,
+    pub limit: u32,
+}
+
+/// Criteria to list recorded actions by. Unset criteria match every action.
+#[derive(Debug, Default)]
+pub struct ActionFilter<'a> {
+    pub repo: Option<&'a str>,
+    pub authorized_by: Option<&'a str>,
+    pub command: Option<&'a str>,
+    pub limit: u32,
+}
+
+/// Criteria to list planned operations by. Unset criteria match every operation.
+#[derive(Debug, Default)]
+pub struct PlannedOperationFilter<'a> {
+    pub repo: Option<&'a str>,
+    pub job_id: Option<&'a str>,
+    pub limit: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobSummary {
+    pub id: String,
+    pub repo: String,
+    pub pull_request: u64,
+    pub event: String,
+    pub command: Option<String>,
+    pub authorized_by: Option<String>,
+    pub status: JobStatus,
+    pub result: Option<String>,
+    pub enqueued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActionEntry {
+    pub job_id: String,
+    pub command: String,
+    pub details: Option<String>,
+    pub authorized_by: String,
+    pub repo: String,
+    pub pull_request: Option<u64>,
+    pub head_sha: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandOutputEntry {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedOperationEntry {
+    pub job_id: Option<String>,
+    pub repo: String,
+    pub operation: String,
+    pub details: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// A job along with everything recorded while it ran.
+#[derive(Debug, Serialize)]
+pub struct JobLog {
+    #[serde(flatten)]
+    pub job: JobSummary,
+    pub actions: Vec<ActionEntry>,
+    pub outputs: Vec<CommandOutputEntry>,
+    pub planned_operations: Vec<PlannedOperationEntry>,
+}
+
+const JOB_SUMMARY_COLUMNS: &str = "
+    j.id, j.repo, j.number, j.event,
+    (SELECT a.command FROM actions a WHERE a.job_id = j.id ORDER BY a.id LIMIT 1),
+    (SELECT a.authorized_by FROM actions a WHERE a.job_id = j.id ORDER BY a.id LIMIT 1),
+    j.status, j.result, j.enqueued_at, j.started_at, j.finished_at";
+
+fn job_summary(row: &Row) -> rusqlite::Result<JobSummary> {
+    let status: String = row.get(6)?;
+    Ok(JobSummary {
+        id: row.get(0)?,
+        repo: row.get(1)?,
+        pull_request: row.get(2)?,
+        event: row.get(3)?,
+        command: row.get(4)?,
+        authorized_by: row.get(5)?,
+        status: status.parse().map_err(|err: anyhow::Error| {
+            rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, err.into())
+        })?,
+        result: row.get(7)?,
+        enqueued_at: row.get(8)?,
+        started_at: row.get(9)?,
+        finished_at: row.get(10)?,
+    })
+}
+
+const ACTION_ENTRY_COLUMNS: &str =
+    "job_id, command, details, authorized_by, repo, pull_request, head_sha, recorded_at";
+
+fn action_entry(row: &Row) -> rusqlite::Result<ActionEntry> {
+    Ok(ActionEntry {
+        job_id: row.get(0)?,
+        command: row.get(1)?,
+        details: row.get(2)?,
+        authorized_by: row.get(3)?,
+        repo: row.get(4)?,
+        pull_request: row.get(5)?,
+        head_sha: row.get(6)?,
+        recorded_at: row.get(7)?,
+    })
+}
+
+const PLANNED_OPERATION_ENTRY_COLUMNS: &str = "job_id, repo, operation, details, recorded_at";
+
+fn planned_operation_entry(row: &Row) -> rusqlite::Result<PlannedOperationEntry> {
+    Ok(PlannedOperationEntry {
+        job_id: row.get(0)?,
+        repo: row.get(1)?,
+        operation: row.get(2)?,
+        details: row.get(3)?,
+        recorded_at: row.get(4)?,
+    })
+}
+
+/// Embedded SQLite database holding the deliveries, jobs and audit trail of botman.
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database {}.", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Records a delivery, forgetting the least recently received ones beyond `capacity` along with
+    /// their finished jobs and audit trail. Returns `false` if the delivery has already been seen,
+    /// unless handling it failed with a server error, in which case the redelivery is handled again.
+    pub fn register_delivery(
+        &self,
+        id: &str,
+        event: &str,
+        repo: &str,
+        capacity: usize,
+    ) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let inserted = tx.execute(
+            "INSERT INTO deliveries (id, event, repo, received_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id) DO UPDATE SET received_at = excluded.received_at, status = NULL
+             WHERE deliveries.status >= 500",
+            params![id, event, repo, Utc::now()],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        // Deliveries beyond the newest `capacity`, except those whose job is yet to finish.
+        tx.execute(
+            "CREATE TEMP TABLE stale_deliveries AS
+             SELECT id FROM deliveries
+             WHERE (received_at, rowid) <= (
+                 SELECT received_at, rowid FROM deliveries
+                 ORDER BY received_at DESC, rowid DESC
+                 LIMIT 1 OFFSET ?1
+             )
+             AND id NOT IN (SELECT id FROM jobs WHERE status IN ('queued', 'running'))",
+            params![capacity as i64],
+        )?;
+        tx.execute_batch(
+            "DELETE FROM actions WHERE job_id IN (SELECT id FROM stale_deliveries);
+             DELETE FROM command_outputs WHERE job_id IN (SELECT id FROM stale_deliveries);
+             DELETE FROM planned_operations WHERE job_id IN (SELECT id FROM stale_deliveries);
+             DELETE FROM jobs WHERE id IN (SELECT id FROM stale_deliveries);
+             DELETE FROM deliveries WHERE id IN (SELECT id FROM stale_deliveries);
+             DROP TABLE stale_deliveries;",
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn set_delivery_status(&self, id: &str, status: u16) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE deliveries SET status = ?2 WHERE id = ?1",
+            params![id, status],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_job(&self, job: &Job) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO jobs (id, repo, number, event, payload, status, enqueued_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                job.id,
+                job.key.repo,
+                job.key.number,
+                job.event,
+                job.payload,
+                JobStatus::Queued.as_str(),
+                job.enqueued_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Jobs that have not run to completion, in the order they were enqueued.
+    pub fn pending_jobs(&self) -> Result<Vec<Job>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, repo, number, event, payload, enqueued_at FROM jobs
+             WHERE status IN ('queued', 'running')
+             ORDER BY enqueued_at, rowid",
+        )?;
+        let jobs = stmt
+            .query_map([], |row| {
+                Ok(Job {
+                    id: row.get(0)?,
+                    key: JobKey {
+                        repo: row.get(1)?,
+                        number: row.get(2)?,
+                    },
+                    event: row.get(3)?,
+                    payload: row.get(4)?,
+                    enqueued_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(jobs)
+    }
+
+    #[cfg(test)]
+    pub fn job_status(&self, id: &str) -> Result<Option<JobStatus>> {
+        let status: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT status FROM jobs WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        status.map(|status| status.parse()).transpose()
+    }
+
+    /// The most recently enqueued jobs matching `filter`.
+    pub fn list_jobs(&self, filter: &JobFilter) -> Result<Vec<JobSummary>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM jobs j
+             WHERE (?1 IS NULL OR j.repo = ?1)
+               AND (?2 IS NULL OR j.number = ?2)
+               AND (?3 IS NULL OR EXISTS (
+                   SELECT 1 FROM actions a WHERE a.job_id = j.id AND a.command = ?3))
+               AND (?4 IS NULL OR j.status = ?4)
+             ORDER BY j.enqueued_at DESC, j.rowid DESC
+             LIMIT ?5",
+            JOB_SUMMARY_COLUMNS
+        ))?;
+        let jobs = stmt
+            .query_map(
+                params![
+                    filter.repo,
+                    filter.pull_request,
+                    filter.command,
+                    filter.status.map(|status| status.as_str()),
+                    filter.limit
+                ],
+                job_summary,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(jobs)
+    }
+
+    pub fn job_log(&self, id: &str) -> Result<Option<JobLog>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(job) = conn
+            .query_row(
+                &format!("SELECT {} FROM jobs j WHERE j.id = ?1", JOB_SUMMARY_COLUMNS),
+                [id],
+                job_summary,
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let actions = conn
+            .prepare(&format!(
+                "SELECT {} FROM actions WHERE job_id = ?1 ORDER BY id",
+                ACTION_ENTRY_COLUMNS
+            ))?
+            .query_map([id], action_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = conn
+            .prepare(
+                "SELECT command, exit_code, stdout, stderr, started_at, finished_at
+                 FROM command_outputs WHERE job_id = ?1 ORDER BY id",
+            )?
+            .query_map([id], |row| {
+                Ok(CommandOutputEntry {
+                    command: row.get(0)?,
+                    exit_code: row.get(1)?,
+                    stdout: row.get(2)?,
+                    stderr: row.get(3)?,
+                    started_at: row.get(4)?,
+                    finished_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let planned_operations = conn
+            .prepare(&format!(
+                "SELECT {} FROM planned_operations WHERE job_id = ?1 ORDER BY id",
+                PLANNED_OPERATION_ENTRY_COLUMNS
+            ))?
+            .query_map([id], planned_operation_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(JobLog {
+            job,
+            actions,
+            outputs,
+            planned_operations,
+        }))
+    }
+
+    /// The most recently recorded actions matching `filter`, i.e. who authorized what.
+    pub fn list_actions(&self, filter: &ActionFilter) -> Result<Vec<ActionEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM actions
+             WHERE (?1 IS NULL OR repo = ?1)
+               AND (?2 IS NULL OR authorized_by = ?2)
+               AND (?3 IS NULL OR command = ?3)
+             ORDER BY id DESC
+             LIMIT ?4",
+            ACTION_ENTRY_COLUMNS
+        ))?;
+        let actions = stmt
+            .query_map(
+                params![
+                    filter.repo,
+                    filter.authorized_by,
+                    filter.command,
+                    filter.limit
+                ],
+                action_entry,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(actions)
+    }
+
+    /// The most recently planned operations matching `filter`.
+    pub fn list_planned_operations(
+        &self,
+        filter: &PlannedOperationFilter,
+    ) -> Result<Vec<PlannedOperationEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM planned_operations
+             WHERE (?1 IS NULL OR repo = ?1)
+               AND (?2 IS NULL OR job_id = ?2)
+             ORDER BY id DESC
+             LIMIT ?3",
+            PLANNED_OPERATION_ENTRY_COLUMNS
+        ))?;
+        let operations = stmt
+            .query_map(
+                params![filter.repo, filter.job_id, filter.limit],
+                planned_operation_entry,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(operations)
+    }
+
+    pub fn start_job(&self, id: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = ?2, started_at = ?3 WHERE id = ?1",
+            params![id, JobStatus::Running.as_str(), Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn finish_job(&self, id: &str, status: JobStatus, result: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE jobs SET status = ?2, result = ?3, finished_at = ?4 WHERE id = ?1",
+            params![id, status.as_str(), result, Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_action(&self, action: &ActionRecord) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO actions (job_id, command, details, authorized_by, repo, pull_request, head_sha, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                action.job_id,
+                action.command,
+                action.details,
+                action.authorized_by,
+                action.repo,
+                action.pull_request,
+                action.head_sha,
+                Utc::now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_command_output(&self, output: &CommandOutputRecord) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO command_outputs (job_id, command, exit_code, stdout, stderr, started_at, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                output.job_id,
+                output.command,
+                output.exit_code,
+                String::from_utf8_lossy(output.stdout),
+                String::from_utf8_lossy(output.stderr),
+                output.started_at,
+                Utc::now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_planned_operation(&self, operation: &PlannedOperationRecord) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO planned_operations (job_id, repo, operation, details, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                operation.job_id,
+                operation.repo,
+                operation.operation,
+                operation.details,
+                Utc::now()
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!(migration = index + 1, "Applying database migration.");
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Database migration {} failed.", index + 1))?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ActionFilter, ActionRecord, CommandOutputRecord, JobFilter, JobStatus,
+        PlannedOperationFilter, PlannedOperationRecord, Store, MIGRATIONS,
+    };
+    use crate::jobs::{Job, JobKey};
+    use chrono::Utc;
+
+    fn job(id: &str) -> Job {
+        Job::new(
+            id.to_owned(),
+            JobKey {
+                repo: "williamboman/mason.nvim".to_owned(),
+                number: 1,
+            },
+            "issue_comment".to_owned(),
+            "{}".to_owned(),
+        )
+    }
+
+    fn register(store: &Store, id: &str, capacity: usize) -> bool {
+        store
+            .register_delivery(id, "issue_comment", "williamboman/mason.nvim", capacity)
+            .unwrap()
+    }
+
+    #[test]
+    fn it_should_run_migrations_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("botman.db");
+        Store::open(&path).unwrap();
+        let store = Store::open(&path).unwrap();
+        let version: usize = store
+            .conn
+            .lock()
+            .unwrap()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn it_should_detect_duplicate_deliveries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        assert!(register(&store, "a", 10));
+        assert!(register(&store, "b", 10));
+        assert!(!register(&store, "a", 10));
+    }
+
+    #[test]
+    fn it_should_handle_redeliveries_of_failed_deliveries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        assert!(register(&store, "a", 10));
+        store.set_delivery_status("a", 503).unwrap();
+        assert!(register(&store, "a", 10));
+        assert!(!register(&store, "a", 10));
+        store.set_delivery_status("a", 202).unwrap();
+        assert!(!register(&store, "a", 10));
+    }
+
+    #[test]
+    fn it_should_forget_oldest_deliveries_beyond_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        for id in ["a", "b", "c", "d", "e", "f"] {
+            assert!(register(&store, id, 2));
+        }
+        assert!(!register(&store, "f", 2));
+        assert!(register(&store, "a", 2));
+    }
+
+    #[test]
+    fn it_should_forget_finished_jobs_with_their_deliveries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        record(&store, "a", 1, "fixup", "williamboman");
+        record(&store, "b", 1, "fixup", "williamboman");
+        store
+            .record_command_output(&CommandOutputRecord {
+                job_id: "a",
+                command: "git push".to_owned(),
+                exit_code: Some(0),
+                stdout: b"",
+                stderr: b"",
+                started_at: Utc::now(),
+            })
+            .unwrap();
+        store
+            .record_planned_operation(&PlannedOperationRecord {
+                job_id: Some("a"),
+                repo: "williamboman/mason.nvim".to_owned(),
+                operation: "push",
+                details: "{}".to_owned(),
+            })
+            .unwrap();
+        store
+            .finish_job("a", JobStatus::Succeeded, "Pushed.")
+            .unwrap();
+        for id in ["c", "d"] {
+            assert!(register(&store, id, 2));
+        }
+
+        assert_eq!(store.job_status("a").unwrap(), None);
+        assert!(store.job_log("a").unwrap().is_none());
+        assert!(store
+            .list_planned_operations(&PlannedOperationFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.job_status("b").unwrap(), Some(JobStatus::Queued));
+        assert!(register(&store, "a", 2));
+        assert!(!register(&store, "b", 2));
+    }
+
+    #[test]
+    fn it_should_remember_deliveries_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("botman.db");
+        {
+            let store = Store::open(&path).unwrap();
+            for id in ["a", "b", "c", "d"] {
+                register(&store, id, 3);
+            }
+        }
+        let store = Store::open(&path).unwrap();
+        assert!(!register(&store, "d", 3));
+        assert!(!register(&store, "b", 3));
+    }
+
+    #[test]
+    fn it_should_track_job_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        for id in ["a", "b"] {
+            register(&store, id, 10);
+            store.insert_job(&job(id)).unwrap();
+        }
+        assert_eq!(store.job_status("a").unwrap(), Some(JobStatus::Queued));
+
+        store.start_job("a").unwrap();
+        assert_eq!(store.job_status("a").unwrap(), Some(JobStatus::Running));
+        assert_eq!(store.pending_jobs().unwrap().len(), 2);
+
+        store
+            .finish_job("a", JobStatus::Succeeded, "Merged base.")
+            .unwrap();
+        let pending: Vec<_> = store
+            .pending_jobs()
+            .unwrap()
+            .into_iter()
+            .map(|job| job.id)
+            .collect();
+        assert_eq!(pending, vec!["b"]);
+        assert_eq!(store.job_status("c").unwrap(), None);
+    }
+
+    fn record(store: &Store, id: &str, number: u64, command: &str, authorized_by: &str) {
+        register(store, id, 10);
+        let mut job = job(id);
+        job.key.number = number;
+        store.insert_job(&job).unwrap();
+        store
+            .record_action(&ActionRecord {
+                job_id: id,
+                command: command.to_owned(),
+                details: format!("{:?}", command),
+                authorized_by,
+                repo: "williamboman/mason.nvim".to_owned(),
+                pull_request: Some(number),
+                head_sha: Some("deadbeef"),
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn it_should_filter_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        record(&store, "a", 1, "fixup", "williamboman");
+        record(&store, "b", 2, "merge-base", "williamboman");
+        record(&store, "c", 2, "fixup", "octocat");
+        store.finish_job("c", JobStatus::Failed, "Boom.").unwrap();
+
+        let ids = |filter: JobFilter| -> Vec<String> {
+            store
+                .list_jobs(&JobFilter {
+                    limit: 10,
+                    ..filter
+                })
+                .unwrap()
+                .into_iter()
+                .map(|job| job.id)
+                .collect()
+        };
+        assert_eq!(ids(JobFilter::default()), vec!["c", "b", "a"]);
+        assert_eq!(
+            ids(JobFilter {
+                pull_request: Some(2),
+                ..Default::default()
+            }),
+            vec!["c", "b"]
+        );
+        assert_eq!(
+            ids(JobFilter {
+                command: Some("fixup"),
+                ..Default::default()
+            }),
+            vec!["c", "a"]
+        );
+        assert_eq!(
+            ids(JobFilter {
+                status: Some(JobStatus::Failed),
+                ..Default::default()
+            }),
+            vec!["c"]
+        );
+        assert!(ids(JobFilter {
+            repo: Some("mason-org/mason-registry"),
+            ..Default::default()
+        })
+        .is_empty());
+        assert_eq!(
+            store
+                .list_jobs(&JobFilter {
+                    limit: 1,
+                    ..Default::default()
+                })
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn it_should_return_job_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        record(&store, "a", 1, "fixup", "williamboman");
+        store
+            .record_command_output(&CommandOutputRecord {
+                job_id: "a",
+                command: "git [\"push\"]".to_owned(),
+                exit_code: Some(1),
+                stdout: b"",
+                stderr: b"rejected",
+                started_at: chrono::Utc::now(),
+            })
+            .unwrap();
+
+        store
+            .record_planned_operation(&PlannedOperationRecord {
+                job_id: Some("a"),
+                repo: "williamboman/mason.nvim".to_owned(),
+                operation: "push",
+                details: r#"{"ref":"fix"}"#.to_owned(),
+            })
+            .unwrap();
+
+        let log = store.job_log("a").unwrap().unwrap();
+        assert_eq!(log.planned_operations[0].operation, "push");
+        let planned_by = |job_id| {
+            store
+                .list_planned_operations(&PlannedOperationFilter {
+                    job_id: Some(job_id),
+                    limit: 10,
+                    ..Default::default()
+                })
+                .unwrap()
+                .len()
+        };
+        assert_eq!(planned_by("a"), 1);
+        assert_eq!(planned_by("b"), 0);
+        assert_eq!(log.job.command.as_deref(), Some("fixup"));
+        assert_eq!(log.job.authorized_by.as_deref(), Some("williamboman"));
+        assert_eq!(log.actions[0].head_sha.as_deref(), Some("deadbeef"));
+        assert_eq!(log.outputs[0].stderr, "rejected");
+        assert!(store.job_log("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn it_should_list_who_authorized_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("botman.db")).unwrap();
+        record(&store, "a", 1, "fixup", "williamboman");
+        record(&store, "b", 2, "merge-base", "octocat");
+
+        let actions = store
+            .list_actions(&ActionFilter {
+                authorized_by: Some("octocat"),
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].command, "merge-base");
+        assert_eq!(actions[0].job_id, "b");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    config,
+    github::{
+        client::{GitHubClient, RequestReviewersDto},
+        data::{
+            GitHubCheckRunConclusion, GitHubCheckRunEvent, GitHubCheckRunStatus, GitHubIssuesEvent,
+            GitHubIssuesEventAction, GitHubPullRequest, GitHubPullRequestEvent,
+            GitHubPullRequestEventAction, GitHubRepo, GitHubUser,
+        },
+    },
+    hacktober::hacktoberfest_label,
+};
+use anyhow::Result;
+use rocket::http::Status;
+use tracing::info;
+
+#[derive(Debug)]
+enum NotifyReason {
+    RenovateFailedCI,
+    NewIssue,
+    NewPullRequest,
+}
+
+impl NotifyReason {
+    fn explain(&self) -> String {
+        format!("`notify(Reason::{:?})`", self)
+    }
+}
+
+fn is_dependency_bot(repo: &GitHubRepo, user: &GitHubUser) -> bool {
+    config::get()
+        .repo(&repo.full_name)
+        .is_some_and(|repo_config| repo_config.dependency_bots.contains(&user.login))
+}
+
+async fn notify_triage(
+    client: &GitHubClient,
+    repo: &GitHubRepo,
+    issue_number: u64,
+    reason: NotifyReason,
+) {
+    let Some(triage_team) = config::get()
+        .repo(&repo.full_name)
+        .and_then(|repo_config| repo_config.triage_team.as_ref())
+    else {
+        return;
+    };
+
+    info!(reason = reason.explain(), "Notifying {}.", triage_team);
+    match reason {
+        NotifyReason::RenovateFailedCI | NotifyReason::NewPullRequest => {
+            let _ = client
+                .request_review(
+                    repo,
+                    issue_number,
+                    &RequestReviewersDto {
+                        reviewers: vec![],
+                        team_reviewers: vec![triage_team.to_owned()],
+                    },
+                )
+                .await;
+        }
+        NotifyReason::NewIssue => {}
+    }
+}
+
+pub async fn issue_event(client: &GitHubClient, event: GitHubIssuesEvent) -> Result<Status> {
+    match event.action {
+        GitHubIssuesEventAction::Opened if event.issue.pull_request.is_none() => {
+            notify_triage(
+                client,
+                &event.repository,
+                event.issue.number,
+                NotifyReason::NewIssue,
+            )
+            .await;
+        }
+        _ => {}
+    }
+    Ok(Status::NoContent)
+}
+
+pub async fn check_run_event(client: &GitHubClient, event: GitHubCheckRunEvent) -> Result<Status> {
+    match event.check_run.status {
+        GitHubCheckRunStatus::Completed
+            if event.check_run.conclusion == Some(GitHubCheckRunConclusion::Failure)
+                || event.check_run.conclusion == Some(GitHubCheckRunConclusion::Cancelled)
+                || event.check_run.conclusion == Some(GitHubCheckRunConclusion::StartupFailure)
+                || event.check_run.conclusion == Some(GitHubCheckRunConclusion::TimedOut) =>
+        {
+            if let Some(check_run_pr) = event.check_run.pull_requests.first() {
+                let pr: GitHubPullRequest = client
+                    .get(&event.repository, &check_run_pr.url)
+                    .await?
+                    .json()
+                    .await?;
+
+                if is_dependency_bot(&event.repository, &pr.user) && pr.requested_teams.is_empty() {
+                    notify_triage(
+                        client,
+                        &event.repository,
+                        pr.number,
+                        NotifyReason::RenovateFailedCI,
+                    )
+                    .await;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(Status::NoContent)
+}
+
+pub async fn pull_request(client: &GitHubClient, event: GitHubPullRequestEvent) -> Result<Status> {
+    hacktoberfest_label(client, &event).await;
+
+    match event.action {
+        GitHubPullRequestEventAction::Opened
+            if !is_dependency_bot(&event.repository, &event.pull_request.user) =>
+        {
+            notify_triage(
+                client,
+                &event.repository,
+                event.pull_request.number,
+                NotifyReason::NewPullRequest,
+            )
+            .await
+        }
+        _ => {}
+    }
+    Ok(Status::NoContent)
+}
@@ -1,28 +1,51 @@
 use crate::{
     github::{
         action::parser::{AuthorizedAction, RawCommand},
-        client,
+        client::{GitHubClient, GraphqlError, GraphqlErrorKind},
         data::{GitHubReaction, GitHubRef},
     },
-    GITHUB_PAT,
+    jobs::JobContext,
+    metrics,
+    store::CommandOutputRecord,
 };
 use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
 use rocket::http::Status;
+use serde_json::json;
 use std::{
-    collections::HashSet, ffi::OsStr, fmt::{Debug, Display}, path::PathBuf, process::Stdio, str::FromStr,
+    collections::HashSet,
+    ffi::OsStr,
+    fmt::{Debug, Display},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::Stdio,
+    str::FromStr,
 };
 use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+/// Whether the bot's own configuration, and so its credentials, may be read from the environment
+/// variable `key`. Those are kept from the processes run in workspaces, which run the pull request's
+/// code.
+fn is_secret_env(key: &str) -> bool {
+    key.starts_with("BOTMAN_") || matches!(key, "GITHUB_PAT" | "GITHUB_WEBHOOK_SECRET")
+}
 
 #[derive(Debug)]
 pub struct Workspace {
+    pub client: GitHubClient,
     pub workdir: TempDir,
     pub base: GitHubRef,
     pub head: GitHubRef,
+    /// The job the workspace belongs to. Spawned processes are recorded to its audit trail, and
+    /// killed once it's cancelled.
+    pub job: Option<JobContext>,
 }
 
 impl Workspace {
     pub async fn create<Command>(
+        client: &GitHubClient,
         action: &AuthorizedAction<Command>,
     ) -> Result<Workspace, (Status, anyhow::Error)>
     where
@@ -30,7 +53,7 @@ impl Workspace {
     {
         let pr = action
             .context
-            .get_pull_request()
+            .get_pull_request(client)
             .await
             .map_err(|err| (Status::InternalServerError, err))?
             .ok_or_else(|| {
@@ -43,48 +66,74 @@ impl Workspace {
                 )
             })?;
 
-        client::create_issue_comment_reaction(
-            action.context.get_repo(),
-            action.context.get_trigger(),
-            &GitHubReaction::PlusOne,
-        )
-        .await
-        .map_err(|err| (Status::ServiceUnavailable, err))?;
-
-        client::minimize_comment(action.context.get_trigger())
+        client
+            .create_comment_reaction(
+                action.context.get_repo(),
+                action.context.get_trigger_kind(),
+                action.context.get_trigger(),
+                &GitHubReaction::PlusOne,
+            )
             .await
             .map_err(|err| (Status::ServiceUnavailable, err))?;
 
+        if action.context.get_trigger_kind().is_minimizable() {
+            if let Err(err) = client
+                .minimize_comment(action.context.get_repo(), action.context.get_trigger())
+                .await
+            {
+                // Minimizing is cosmetic, don't fail the command over a comment that was deleted
+                // or can't be minimized with our permissions.
+                match GraphqlError::find(&err) {
+                    Some(graphql_err)
+                        if graphql_err.is(GraphqlErrorKind::NotFound)
+                            || graphql_err.is(GraphqlErrorKind::Forbidden) =>
+                    {
+                        warn!(error = %err, "Failed to minimize comment, continuing.");
+                    }
+                    _ => return Err((Status::ServiceUnavailable, err)),
+                }
+            }
+        }
+
         let base = pr.base;
         let head = pr.head;
 
         let workspace = Workspace {
+            client: client.clone(),
             workdir: tempfile::tempdir()
                 .map_err(|err| (Status::InternalServerError, anyhow!(err)))?,
             head,
             base,
+            job: action.job.clone(),
         };
 
-        async {
+        metrics::workspace_stage("clone", async {
             workspace.clone_repo().await?;
             workspace.checkout_ref().await?;
             Ok::<(), anyhow::Error>(())
-        }
+        })
         .await
         .map_err(|err| (Status::InternalServerError, err))?;
         Ok(workspace)
     }
 
     pub async fn commit(&self, commit_msg: &str) -> Result<()> {
-        println!("Committing changes…");
+        info!("Committing changes…");
         self.spawn("git", ["add", "."]).await?;
         self.spawn("git", ["commit", "-m", commit_msg]).await?;
         Ok(())
     }
 
     pub async fn push(&self) -> Result<()> {
-        println!("Pushing changes…");
-        self.spawn("git", ["push"]).await?;
+        info!("Pushing changes…");
+        if self.client.skip_in_dry_run(
+            &self.head.repo.full_name,
+            "push",
+            json!({ "ref": self.head.r#ref }),
+        ) {
+            return Ok(());
+        }
+        metrics::workspace_stage("push", self.spawn_authenticated(["push"])).await?;
         Ok(())
     }
 
@@ -105,8 +154,12 @@ impl Workspace {
     }
 
     pub async fn merge_with_base(&self) -> Result<()> {
-        println!("Merging with {}", self.base.r#ref);
-        self.spawn("git", ["fetch", "upstream", &self.base.r#ref])
+        metrics::workspace_stage("merge", self.merge_base_ref()).await
+    }
+
+    async fn merge_base_ref(&self) -> Result<()> {
+        info!("Merging with {}", self.base.r#ref);
+        self.spawn_authenticated(["fetch", "upstream", &self.base.r#ref])
             .await?;
         let base_ref = &format!("upstream/{}", self.base.r#ref);
         self.spawn(
@@ -126,24 +179,15 @@ impl Workspace {
     }
 
     async fn clone_repo(&self) -> Result<()> {
-        println!("Cloning {:?}…", self.head.repo.full_name);
-        self.spawn(
-            "git",
-            [
-                "clone",
-                "-c",
-                format!(
-                    "http.https://github.com/.extraheader=AUTHORIZATION: basic {}",
-                    base64::encode(format!("x-access-token:{}", GITHUB_PAT.as_str()))
-                )
-                .as_str(),
-                "-c",
-                "checkout.defaultRemote=origin",
-                "--",
-                self.head.repo.as_git_url().as_str(),
-                ".",
-            ],
-        )
+        info!("Cloning {}…", self.head.repo.full_name);
+        self.spawn_authenticated([
+            "clone",
+            "-c",
+            "checkout.defaultRemote=origin",
+            "--",
+            self.client.repo_git_url(&self.head.repo.full_name).as_str(),
+            ".",
+        ])
         .await?;
         self.spawn(
             "git",
@@ -151,16 +195,39 @@ impl Workspace {
                 "remote",
                 "add",
                 "upstream",
-                self.base.repo.as_git_url().as_str(),
+                self.client.repo_git_url(&self.base.repo.full_name).as_str(),
             ],
         )
         .await?;
-        self.spawn("git", ["fetch", "upstream"]).await?;
+        self.spawn_authenticated(["fetch", "upstream"]).await?;
         Ok(())
     }
 
+    /// Runs a git command talking to the remotes. The credentials are passed through git's
+    /// environment configuration for this invocation only, so they never end up in the command
+    /// line, the audit trail or the clone's `.git/config`.
+    async fn spawn_authenticated<'a, I>(&self, args: I) -> Result<std::process::Output>
+    where
+        I: IntoIterator<Item = &'a str> + Debug + Clone,
+    {
+        let token = self.client.token(&self.base.repo.full_name).await?;
+        let header = format!(
+            "AUTHORIZATION: basic {}",
+            base64::encode(format!("x-access-token:{}", token))
+        );
+        let envs = [
+            ("GIT_CONFIG_COUNT", "1".to_owned()),
+            (
+                "GIT_CONFIG_KEY_0",
+                format!("http.{}/.extraheader", self.client.git_url()),
+            ),
+            ("GIT_CONFIG_VALUE_0", header),
+        ];
+        self.spawn_with_env("git", args, None, &envs).await
+    }
+
     async fn checkout_ref(&self) -> Result<()> {
-        println!("Checking out {}", self.head.r#ref);
+        info!("Checking out {}", self.head.r#ref);
         let _ = self
             .spawn("git", ["checkout", self.head.r#ref.as_str()])
             .await?;
@@ -185,13 +252,88 @@ impl Workspace {
         I: IntoIterator<Item = S> + Debug + Clone,
         S: AsRef<OsStr> + Display,
     {
-        let mut child = tokio::process::Command::new(&cmd)
-            .current_dir(&self.workdir.path())
-            .args(args.clone())
+        self.spawn_with_env(cmd, args, stdin, &[]).await
+    }
+
+    async fn spawn_with_env<I, S>(
+        &self,
+        cmd: S,
+        args: I,
+        stdin: Option<Vec<u8>>,
+        envs: &[(&str, String)],
+    ) -> Result<std::process::Output>
+    where
+        I: IntoIterator<Item = S> + Debug + Clone,
+        S: AsRef<OsStr> + Display,
+    {
+        let command_line = format!("{} {:?}", cmd, args);
+        let span = info_span!(
+            "spawn",
+            command = %command_line,
+            exit_code = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let started_at = Utc::now();
+        let output = self
+            .wait_for_child(&cmd, args, stdin, envs)
+            .instrument(span.clone())
+            .await?;
+        span.record("exit_code", output.status.code());
+        span.record("duration_ms", (Utc::now() - started_at).num_milliseconds());
+
+        if let Some(job) = &self.job {
+            let record = CommandOutputRecord {
+                job_id: &job.id,
+                command: command_line,
+                exit_code: output.status.code(),
+                stdout: &output.stdout,
+                stderr: &output.stderr,
+                started_at,
+            };
+            if let Err(err) = job.store.record_command_output(&record) {
+                error!(parent: &span, error = ?err, "Failed to record command output.");
+            }
+        }
+
+        if output.status.success() {
+            Ok(output)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            error!(parent: &span, %stderr, "Command failed.");
+            bail!("{} failed: {}", cmd, stderr)
+        }
+    }
+
+    async fn wait_for_child<I, S>(
+        &self,
+        cmd: &S,
+        args: I,
+        stdin: Option<Vec<u8>>,
+        envs: &[(&str, String)],
+    ) -> Result<std::process::Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr> + Display,
+    {
+        let mut command = std::process::Command::new(cmd);
+        for (key, _) in std::env::vars_os() {
+            if key.to_str().is_some_and(is_secret_env) {
+                command.env_remove(key);
+            }
+        }
+        // Gives the child its own process group, so cancelling also kills what it spawned.
+        command.process_group(0);
+        let mut command = tokio::process::Command::from(command);
+        let mut child = command
+            .current_dir(self.workdir.path())
+            .args(args)
+            .envs(envs.iter().map(|(key, value)| (key, value)))
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()?;
+        let process_group = child.id();
 
         if let Some(stdin_buffer) = stdin {
             let mut stdin_handle = child
@@ -203,14 +345,255 @@ impl Workspace {
             stdin_handle.shutdown().await?;
         }
 
-        let output = child.wait_with_output().await?;
+        let cancelled = async {
+            match &self.job {
+                Some(job) => job.cancellation.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        Ok(tokio::select! {
+            output = child.wait_with_output() => output?,
+            _ = cancelled => {
+                if let Some(process_group) = process_group {
+                    kill_process_group(process_group);
+                }
+                bail!("{} was cancelled.", cmd)
+            }
+        })
+    }
+}
 
-        if output.status.success() {
-            Ok(output)
-        } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            eprintln!("cmd {} {:?} failed: {}", cmd, args, stderr);
-            bail!("{} failed: {}", cmd, stderr)
+fn kill_process_group(process_group: u32) {
+    // SAFETY: kill(2) has no memory safety preconditions. A negative pid addresses the group.
+    if unsafe { libc::kill(-(process_group as libc::pid_t), libc::SIGKILL) } != 0 {
+        warn!(
+            process_group,
+            error = %std::io::Error::last_os_error(),
+            "Failed to kill process group."
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command, sync::Arc, time::Duration};
+
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio_util::sync::CancellationToken;
+
+    use super::Workspace;
+    use crate::{
+        github::{
+            action::{
+                authorization::AuthorizedUser,
+                parser::{AuthorizedAction, RawCommand},
+            },
+            auth::Credentials,
+            client::{http_client, GitHubClient},
+            data::GitHubPullRequestReview,
+        },
+        jobs::{Job, JobContext, JobKey},
+        store::Store,
+    };
+
+    struct NoCommand;
+
+    impl TryFrom<RawCommand> for NoCommand {
+        type Error = anyhow::Error;
+
+        fn try_from(_: RawCommand) -> Result<Self, Self::Error> {
+            anyhow::bail!("No commands here.")
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .current_dir(dir)
+            .args([
+                "-c",
+                "user.name=botman",
+                "-c",
+                "user.email=botman@example.com",
+            ])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// Serves williamboman/mason.nvim from a local bare repo, with a `main` branch.
+    fn remote(dir: &Path) {
+        let work = dir.join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        git(&work, &["init", "-b", "main"]);
+        std::fs::write(work.join("README.md"), "mason.nvim").unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-m", "init"]);
+        git(
+            dir,
+            &["clone", "--bare", "work", "williamboman/mason.nvim.git"],
+        );
+    }
+
+    fn pull_request_ref() -> serde_json::Value {
+        json!({
+            "ref": "main",
+            "sha": "0000000000000000000000000000000000000000",
+            "user": { "id": 1, "login": "williamboman" },
+            "repo": { "id": 1, "full_name": "williamboman/mason.nvim" },
+        })
+    }
+
+    struct Fixture {
+        _remotes: TempDir,
+        _state: TempDir,
+        store: Arc<Store>,
+        client: GitHubClient,
+        action: AuthorizedAction<NoCommand>,
+        cancellation: CancellationToken,
+    }
+
+    fn fixture() -> Fixture {
+        let remotes = tempfile::tempdir().unwrap();
+        remote(remotes.path());
+        let state = tempfile::tempdir().unwrap();
+        let store = Arc::new(Store::open(&state.path().join("botman.db")).unwrap());
+        store
+            .register_delivery("1", "pull_request_review", "williamboman/mason.nvim", 10)
+            .unwrap();
+        store
+            .insert_job(&Job::new(
+                "1".to_owned(),
+                JobKey {
+                    repo: "williamboman/mason.nvim".to_owned(),
+                    number: 1,
+                },
+                "pull_request_review".to_owned(),
+                "{}".to_owned(),
+            ))
+            .unwrap();
+        let client = GitHubClient::new(
+            http_client(),
+            "https://api.github.example.com",
+            "https://api.github.example.com/graphql",
+            &format!("file://{}", remotes.path().display()),
+            Credentials::Token("ghp_secret".to_owned()),
+        )
+        .with_dry_run(store.clone());
+        let review: GitHubPullRequestReview = serde_json::from_value(json!({
+            "action": "submitted",
+            "review": {
+                "id": 1,
+                "node_id": "PRR_1",
+                "body": "@williambotman /fixup",
+                "user": { "id": 1, "login": "williamboman" },
+            },
+            "pull_request": {
+                "id": 1,
+                "number": 1,
+                "head": pull_request_ref(),
+                "base": pull_request_ref(),
+                "merged": false,
+                "user": { "id": 1, "login": "williamboman" },
+                "requested_teams": [],
+                "updated_at": "2024-01-01T00:00:00Z",
+            },
+            "repository": { "id": 1, "full_name": "williamboman/mason.nvim" },
+        }))
+        .unwrap();
+        let cancellation = CancellationToken::new();
+        let action = AuthorizedAction::<NoCommand> {
+            actions: vec![],
+            context: Box::new(review),
+            authorized_by: AuthorizedUser("williamboman".to_owned()),
+            job: Some(JobContext {
+                id: "1".to_owned(),
+                cancellation: cancellation.clone(),
+                store: store.clone(),
+            }),
+        };
+        Fixture {
+            _remotes: remotes,
+            _state: state,
+            store,
+            client,
+            action,
+            cancellation,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_credentials_out_of_the_workdir_and_audit_trail() {
+        let fixture = fixture();
+        let Fixture {
+            store,
+            client,
+            action,
+            ..
+        } = &fixture;
+
+        let workspace = Workspace::create(client, action).await.unwrap();
+        workspace.merge_with_base().await.unwrap();
+
+        let git_config =
+            std::fs::read_to_string(workspace.workdir.path().join(".git/config")).unwrap();
+        assert!(!git_config.contains("extraheader"), "{}", git_config);
+
+        let outputs = store.job_log("1").unwrap().unwrap().outputs;
+        assert!(outputs
+            .iter()
+            .any(|output| output.command.starts_with("git [\"clone\"")));
+        for output in outputs {
+            assert!(
+                !output.command.contains("extraheader"),
+                "{}",
+                output.command
+            );
+            assert!(
+                !output.command.contains("AUTHORIZATION"),
+                "{}",
+                output.command
+            );
+        }
+    }
+
+    fn is_running(pid: &str) -> bool {
+        // Nothing may reap the orphaned grandchild, so a zombie counts as killed.
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| stat.split_whitespace().nth(2) != Some("Z"))
+    }
+
+    #[tokio::test]
+    async fn it_should_kill_the_processes_a_cancelled_command_spawned() {
+        let fixture = fixture();
+        let Fixture {
+            client,
+            action,
+            cancellation,
+            ..
+        } = &fixture;
+        let workspace = Workspace::create(client, action).await.unwrap();
+        let pid_file = workspace.workdir.path().join("grandchild.pid");
+
+        let command = workspace.spawn("sh", ["-c", "sleep 60 & echo $! > grandchild.pid; wait"]);
+        let cancel = async {
+            while !pid_file.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            cancellation.cancel();
+        };
+        let (result, ()) = tokio::join!(command, cancel);
+        assert!(result.is_err());
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let pid = pid.trim();
+        for _ in 0..100 {
+            if !is_running(pid) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
+        panic!("sleep {} outlived the cancelled command", pid);
     }
 }